@@ -0,0 +1,400 @@
+use crate::image_handler::backdrop::BackdropKind;
+use crate::image_handler::hdr::ToneMapping;
+use crate::image_handler::inspector::ColorFormat;
+use crate::image_handler::overlay::OsdPosition;
+use crate::image_handler::slideshow;
+use crate::image_handler::transition::TransitionKind;
+use crate::image_handler::window_level::Lut;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// User settings read from `$XDG_CONFIG_HOME/iw/config`.
+///
+/// The file holds one `key = value` pair per line, `#` starts a comment.
+/// Unknown keys and malformed values are reported and the default is kept.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// number of threads decoding images
+    pub decode_workers: usize,
+    /// memory budget of the decoded image cache in megabytes
+    pub cache_max_mb: usize,
+    /// max number of decoded images kept in the cache
+    pub cache_max_images: usize,
+    /// number of neighbour images decoded ahead on each side
+    pub prefetch: usize,
+    /// zoom above which the image is drawn with nearest neighbour interpolation
+    pub nearest_above_zoom: f64,
+    /// draw the pixel grid
+    pub pixel_grid: bool,
+    /// min width of an image pixel on screen for the pixel grid to be drawn
+    pub pixel_grid_min_zoom: f64,
+    /// factor applied by one zoom step
+    pub zoom_step: f64,
+    /// zoom levels a zoom step snaps to, as scales (1.0 is 100%)
+    pub zoom_presets: Vec<f64>,
+    /// min zoom as a scale
+    pub zoom_min: f64,
+    /// max zoom as a scale
+    pub zoom_max: f64,
+    /// distance in screen pixels panned by one key press
+    pub pan_step: i32,
+    /// show which part of a zoomed image is visible
+    pub scroll_indicators: bool,
+    /// keep the image moving after a fast drag
+    pub kinetic_panning: bool,
+    /// animate zoom changes over a few frames
+    pub animated_zoom: bool,
+    /// background around the image in fullscreen
+    pub fullscreen_background: gdk::RGBA,
+    /// fill under transparent pixels: checkerboard, black, white or custom
+    pub backdrop: BackdropKind,
+    /// color of the `custom` backdrop
+    pub backdrop_color: gdk::RGBA,
+    /// size of the checkerboard squares in screen pixels
+    pub checkerboard_size: u32,
+    /// light squares of the checkerboard
+    pub checkerboard_light: gdk::RGBA,
+    /// dark squares of the checkerboard
+    pub checkerboard_dark: gdk::RGBA,
+    /// idle time in ms after which the cursor is hidden in fullscreen, 0 keeps it visible
+    pub cursor_hide_ms: u32,
+    /// seconds each image is shown in the slideshow
+    pub slideshow_interval: f64,
+    /// start over after the last image instead of stopping the slideshow
+    pub slideshow_loop: bool,
+    /// random slideshow order, every image is shown once before any repeats
+    pub slideshow_shuffle: bool,
+    /// transition between slideshow images: none, crossfade or slide
+    pub slideshow_transition: TransitionKind,
+    /// length of a slideshow transition in ms
+    pub slideshow_transition_ms: u32,
+    /// initial size of the gallery thumbnails in pixels, up to 512
+    pub thumbnail_size: i32,
+    /// number of threads generating thumbnails
+    pub thumbnail_workers: usize,
+    /// memory budget of the thumbnail cache in megabytes
+    pub thumbnail_cache_mb: usize,
+    /// read and write thumbnails in `$XDG_CACHE_HOME/thumbnails`, shared with other applications
+    pub shared_thumbnails: bool,
+    /// show the filmstrip under the image
+    pub filmstrip: bool,
+    /// height of the filmstrip in pixels
+    pub filmstrip_height: i32,
+    /// show the on-screen info overlay
+    pub osd: bool,
+    /// corner of the OSD: top-left, top-right, bottom-left or bottom-right
+    pub osd_position: OsdPosition,
+    /// text of the OSD, see `ImageInfo::expand` for the placeholders
+    pub osd_template: String,
+    /// window title, with the placeholders of the OSD
+    pub title_template: String,
+    /// show the status bar under the image
+    pub status_bar: bool,
+    /// text of the status bar, with the placeholders of the OSD
+    pub status_bar_template: String,
+    /// show the histogram
+    pub histogram: bool,
+    /// count only the part of the image visible at the current zoom
+    pub histogram_visible_region: bool,
+    /// corner of the histogram
+    pub histogram_position: OsdPosition,
+    /// convert the colors of tagged images to the display profile
+    pub color_management: bool,
+    /// ICC profile of the display, sRGB when not set
+    pub display_profile: Option<PathBuf>,
+    /// stops high precision images are exposed by
+    pub exposure: f32,
+    /// gamma of the display high precision images are encoded for
+    pub display_gamma: f32,
+    /// tone mapping of high precision images: clamp, reinhard or aces
+    pub tone_mapping: ToneMapping,
+    /// window high precision grayscale images from percentiles of their values when opened
+    pub auto_window: bool,
+    /// percentile shown black by the automatic window
+    pub auto_window_low: f32,
+    /// percentile shown white by the automatic window
+    pub auto_window_high: f32,
+    /// keep the window of grayscale images for the next image instead of starting over
+    pub keep_window: bool,
+    /// lookup table of grayscale images: gray, viridis, inferno or jet
+    pub lut: Lut,
+    /// corner of the pixel inspector readout
+    pub inspector_position: OsdPosition,
+    /// notation of the colour copied by a click in the pixel inspector: hex, rgb or float
+    pub inspector_copy_format: ColorFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            decode_workers: 2,
+            cache_max_mb: 256,
+            cache_max_images: 16,
+            prefetch: 2,
+            nearest_above_zoom: 4.0,
+            pixel_grid: true,
+            pixel_grid_min_zoom: 8.0,
+            zoom_step: 1.25,
+            zoom_presets: vec![0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0],
+            zoom_min: 0.01,
+            zoom_max: 64.0,
+            pan_step: 64,
+            scroll_indicators: true,
+            kinetic_panning: true,
+            animated_zoom: true,
+            fullscreen_background: gdk::RGBA::black(),
+            backdrop: BackdropKind::Checkerboard,
+            backdrop_color: gray(0.5),
+            checkerboard_size: 8,
+            checkerboard_light: gray(0.8),
+            checkerboard_dark: gray(0.6),
+            cursor_hide_ms: 1500,
+            slideshow_interval: 5.0,
+            slideshow_loop: true,
+            slideshow_shuffle: false,
+            slideshow_transition: TransitionKind::None,
+            slideshow_transition_ms: 400,
+            thumbnail_size: 128,
+            thumbnail_workers: 2,
+            thumbnail_cache_mb: 128,
+            shared_thumbnails: true,
+            filmstrip: false,
+            filmstrip_height: 96,
+            osd: false,
+            osd_position: OsdPosition::TopLeft,
+            osd_template: String::from(
+                "{name}\\n{index}/{total}  {w}x{h}  {size}\\n{zoom}%  {format}  {load}",
+            ),
+            title_template: String::from("{path}"),
+            status_bar: false,
+            status_bar_template: String::from("{index}/{total}  {name}  {w}x{h}  {zoom}%  {size}"),
+            histogram: false,
+            histogram_visible_region: false,
+            histogram_position: OsdPosition::BottomRight,
+            color_management: true,
+            display_profile: None,
+            exposure: 0.0,
+            display_gamma: 2.2,
+            tone_mapping: ToneMapping::Clamp,
+            auto_window: false,
+            auto_window_low: 0.5,
+            auto_window_high: 99.5,
+            keep_window: false,
+            lut: Lut::Gray,
+            inspector_position: OsdPosition::BottomLeft,
+            inspector_copy_format: ColorFormat::Hex,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        glib::get_user_config_dir().map(|dir| dir.join("iw").join("config"))
+    }
+
+    /// Load the config file, falling back to defaults when it does not exist.
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return config,
+        };
+        let src = match read_to_string(&path) {
+            Ok(src) => src,
+            Err(_) => return config,
+        };
+        for (n, err) in config.read(&src) {
+            eprintln!("{:?}:{}: {}", path, n, err);
+        }
+        config
+    }
+
+    /// Apply the `key = value` lines of `src`, returns the errors with their line numbers.
+    fn read(&mut self, src: &str) -> Vec<(usize, String)> {
+        let mut errors = Vec::new();
+        for (n, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut kv = line.splitn(2, '=');
+            let key = kv.next().unwrap().trim();
+            let value = match kv.next() {
+                Some(value) => strip_comment(value.trim()).trim(),
+                None => {
+                    errors.push((n + 1, String::from("expected `key = value`")));
+                    continue;
+                }
+            };
+            if let Err(err) = self.set(key, value) {
+                errors.push((n + 1, err));
+            }
+        }
+        errors
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "decode_workers" => self.decode_workers = parse(key, value)?,
+            "cache_max_mb" => self.cache_max_mb = parse(key, value)?,
+            "cache_max_images" => self.cache_max_images = parse(key, value)?,
+            "prefetch" => self.prefetch = parse(key, value)?,
+            "nearest_above_zoom" => self.nearest_above_zoom = parse(key, value)?,
+            "pixel_grid" => self.pixel_grid = parse(key, value)?,
+            "pixel_grid_min_zoom" => self.pixel_grid_min_zoom = parse(key, value)?,
+            "zoom_step" => self.zoom_step = parse(key, value)?,
+            "zoom_presets" => self.zoom_presets = parse_list(key, value)?,
+            "zoom_min" => self.zoom_min = parse(key, value)?,
+            "zoom_max" => self.zoom_max = parse(key, value)?,
+            "pan_step" => self.pan_step = parse(key, value)?,
+            "scroll_indicators" => self.scroll_indicators = parse(key, value)?,
+            "kinetic_panning" => self.kinetic_panning = parse(key, value)?,
+            "animated_zoom" => self.animated_zoom = parse(key, value)?,
+            "fullscreen_background" => self.fullscreen_background = parse(key, value)?,
+            "backdrop" => self.backdrop = parse(key, value)?,
+            "backdrop_color" => self.backdrop_color = parse(key, value)?,
+            "checkerboard_size" => self.checkerboard_size = parse(key, value)?,
+            "checkerboard_light" => self.checkerboard_light = parse(key, value)?,
+            "checkerboard_dark" => self.checkerboard_dark = parse(key, value)?,
+            "cursor_hide_ms" => self.cursor_hide_ms = parse(key, value)?,
+            "slideshow_interval" => self.slideshow_interval = parse_interval(key, value)?,
+            "slideshow_loop" => self.slideshow_loop = parse(key, value)?,
+            "slideshow_shuffle" => self.slideshow_shuffle = parse(key, value)?,
+            "slideshow_transition" => self.slideshow_transition = parse(key, value)?,
+            "slideshow_transition_ms" => self.slideshow_transition_ms = parse(key, value)?,
+            "thumbnail_size" => self.thumbnail_size = parse(key, value)?,
+            "thumbnail_workers" => self.thumbnail_workers = parse(key, value)?,
+            "thumbnail_cache_mb" => self.thumbnail_cache_mb = parse(key, value)?,
+            "shared_thumbnails" => self.shared_thumbnails = parse(key, value)?,
+            "filmstrip" => self.filmstrip = parse(key, value)?,
+            "filmstrip_height" => self.filmstrip_height = parse(key, value)?,
+            "osd" => self.osd = parse(key, value)?,
+            "osd_position" => self.osd_position = parse(key, value)?,
+            "osd_template" => self.osd_template = parse(key, value)?,
+            "title_template" => self.title_template = parse(key, value)?,
+            "status_bar" => self.status_bar = parse(key, value)?,
+            "status_bar_template" => self.status_bar_template = parse(key, value)?,
+            "histogram" => self.histogram = parse(key, value)?,
+            "histogram_visible_region" => self.histogram_visible_region = parse(key, value)?,
+            "histogram_position" => self.histogram_position = parse(key, value)?,
+            "color_management" => self.color_management = parse(key, value)?,
+            "display_profile" => self.display_profile = Some(PathBuf::from(value)),
+            "exposure" => self.exposure = parse(key, value)?,
+            "display_gamma" => self.display_gamma = parse(key, value)?,
+            "tone_mapping" => self.tone_mapping = parse(key, value)?,
+            "auto_window" => self.auto_window = parse(key, value)?,
+            "auto_window_low" => self.auto_window_low = parse(key, value)?,
+            "auto_window_high" => self.auto_window_high = parse(key, value)?,
+            "keep_window" => self.keep_window = parse(key, value)?,
+            "lut" => self.lut = parse(key, value)?,
+            "inspector_position" => self.inspector_position = parse(key, value)?,
+            "inspector_copy_format" => self.inspector_copy_format = parse(key, value)?,
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+        Ok(())
+    }
+}
+
+fn gray(level: f64) -> gdk::RGBA {
+    gdk::RGBA {
+        red: level,
+        green: level,
+        blue: level,
+        alpha: 1.0,
+    }
+}
+
+/// `value` up to a trailing comment, a `#` following whitespace.
+/// A `#` starting the value is kept, hex colors like `#202020` are values.
+fn strip_comment(value: &str) -> &str {
+    let mut previous = None;
+    for (i, c) in value.char_indices() {
+        if c == '#' && previous.is_some_and(char::is_whitespace) {
+            return &value[..i];
+        }
+        previous = Some(c);
+    }
+    value
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{}` for `{}`", value, key))
+}
+
+/// Parse the seconds of a slideshow interval, above 0 and at most a day.
+fn parse_interval(key: &str, value: &str) -> Result<f64, String> {
+    let seconds = parse(key, value)?;
+    match slideshow::interval(seconds) {
+        Some(_) => Ok(seconds),
+        None => Err(format!(
+            "`{}` must be above 0 and at most {} seconds",
+            key,
+            slideshow::MAX_INTERVAL
+        )),
+    }
+}
+
+/// Parse a comma separated list, e.g. `0.5, 1, 2`.
+fn parse_list<T: FromStr>(key: &str, value: &str) -> Result<Vec<T>, String> {
+    value
+        .split(',')
+        .map(|item| parse(key, item.trim()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trailing_comments_follow_whitespace() {
+        assert_eq!(strip_comment("3 # more"), "3 ");
+        assert_eq!(strip_comment("3# more"), "3# more");
+        assert_eq!(strip_comment("#202020"), "#202020");
+        assert_eq!(strip_comment("#202020 # dark gray"), "#202020 ");
+    }
+
+    #[test]
+    fn reads_hex_fullscreen_background() {
+        let mut config = Config::default();
+        let errors = config.read("fullscreen_background = #202020 # dark gray\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.fullscreen_background, "#202020".parse().unwrap());
+    }
+
+    #[test]
+    fn reads_hex_backdrop_colors() {
+        let mut config = Config::default();
+        let errors = config.read(
+            "backdrop_color = #808080\ncheckerboard_light = #cccccc\ncheckerboard_dark = #999999\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.backdrop_color, "#808080".parse().unwrap());
+        assert_eq!(config.checkerboard_light, "#cccccc".parse().unwrap());
+        assert_eq!(config.checkerboard_dark, "#999999".parse().unwrap());
+    }
+
+    #[test]
+    fn rejects_endless_slideshow_intervals() {
+        let mut config = Config::default();
+        for value in ["inf", "1e300", "NaN", "0", "-5"] {
+            let errors = config.read(&format!("slideshow_interval = {}\n", value));
+            assert_eq!(errors.len(), 1, "{}", value);
+        }
+        assert_eq!(config.slideshow_interval, 5.0);
+        assert!(config.read("slideshow_interval = 2.5\n").is_empty());
+        assert_eq!(config.slideshow_interval, 2.5);
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let mut config = Config::default();
+        let errors = config.read("# header\n  # indented\nprefetch = 3\nprefetch\nnope = 1\n");
+        assert_eq!(config.prefetch, 3);
+        let lines: Vec<usize> = errors.iter().map(|(n, _)| *n).collect();
+        assert_eq!(lines, vec![4, 5]);
+    }
+}
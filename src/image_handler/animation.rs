@@ -0,0 +1,125 @@
+// length of an animated zoom transition
+const ZOOM_DURATION_US: f64 = 150_000.0;
+// fraction of the fling velocity left after one second
+const FRICTION_PER_SECOND: f64 = 0.02;
+// below this speed in screen pixels per second a fling stops
+const MIN_SPEED: f64 = 20.0;
+// a drag released slower than this does not fling
+const MIN_FLING_SPEED: f64 = 200.0;
+// a drag held still this long before release does not fling
+const MAX_REST_MS: u32 = 50;
+
+/// Smoothed velocity of the pointer while dragging.
+#[derive(Debug, Default)]
+pub struct DragVelocity {
+    velocity: [f64; 2],
+    last_time: u32,
+}
+
+impl DragVelocity {
+    /// Start tracking a new drag at event time `time` in ms.
+    pub fn reset(&mut self, time: u32) {
+        self.velocity = [0.0, 0.0];
+        self.last_time = time;
+    }
+
+    /// Account for a pointer move of `delta` screen pixels at event time `time`.
+    pub fn update(&mut self, delta: [f64; 2], time: u32) {
+        let dt = time.wrapping_sub(self.last_time);
+        if dt == 0 {
+            return;
+        }
+        let dt = dt as f64 / 1000.0;
+        for (velocity, delta) in self.velocity.iter_mut().zip(&delta) {
+            // favour the last moves, the first ones of a drag are often jerky
+            *velocity = 0.6 * delta / dt + 0.4 * *velocity;
+        }
+        self.last_time = time;
+    }
+
+    /// Velocity at release time `time`, `None` when it is too slow to fling.
+    pub fn fling(&self, time: u32) -> Option<[f64; 2]> {
+        if time.wrapping_sub(self.last_time) > MAX_REST_MS {
+            return None;
+        }
+        let [vx, vy] = self.velocity;
+        if vx.hypot(vy) < MIN_FLING_SPEED {
+            return None;
+        }
+        Some(self.velocity)
+    }
+}
+
+/// Inertial movement of the image after a fast drag, decaying with friction.
+#[derive(Debug)]
+pub struct Kinetic {
+    velocity: [f64; 2],
+    // fraction of a pixel not moved yet
+    remainder: [f64; 2],
+    last_frame: Option<i64>,
+}
+
+impl Kinetic {
+    pub fn new(velocity: [f64; 2]) -> Self {
+        Self {
+            velocity,
+            remainder: [0.0, 0.0],
+            last_frame: None,
+        }
+    }
+
+    /// Whole pixels to move the image by at `frame_time` of the frame clock in µs,
+    /// `None` once the movement has stopped.
+    pub fn step(&mut self, frame_time: i64) -> Option<[i32; 2]> {
+        let last_frame = self.last_frame.replace(frame_time).unwrap_or(frame_time);
+        let dt = (frame_time - last_frame) as f64 / 1_000_000.0;
+        let decay = FRICTION_PER_SECOND.powf(dt);
+        let mut delta = [0, 0];
+        let axes = self.velocity.iter_mut().zip(self.remainder.iter_mut());
+        for ((velocity, remainder), delta) in axes.zip(delta.iter_mut()) {
+            *velocity *= decay;
+            let moved = *remainder + *velocity * dt;
+            *delta = moved.trunc() as i32;
+            *remainder = moved.fract();
+        }
+        let [vx, vy] = self.velocity;
+        if vx.hypot(vy) < MIN_SPEED {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+}
+
+/// Transition between two zoom levels, interpolated on a log scale
+/// so that the apparent speed is the same when zooming in and out.
+#[derive(Debug)]
+pub struct ZoomAnimation {
+    from: f64,
+    pub to: f64,
+    start: Option<i64>,
+}
+
+impl ZoomAnimation {
+    pub fn new(from: f64, to: f64) -> Self {
+        Self {
+            from,
+            to,
+            start: None,
+        }
+    }
+
+    /// Scale at `frame_time` of the frame clock in µs and whether the transition is over.
+    pub fn scale_at(&mut self, frame_time: i64) -> (f64, bool) {
+        let start = *self.start.get_or_insert(frame_time);
+        let t = ((frame_time - start) as f64 / ZOOM_DURATION_US).min(1.0);
+        // ease out, fast at first to feel responsive
+        let eased = 1.0 - (1.0 - t).powi(3);
+        let scale = self.from * (self.to / self.from).powf(eased);
+        if t >= 1.0 {
+            (self.to, true)
+        } else {
+            (scale, false)
+        }
+    }
+}
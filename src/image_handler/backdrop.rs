@@ -0,0 +1,118 @@
+use crate::config::Config;
+use std::fmt;
+use std::str::FromStr;
+
+/// What shows through the transparent parts of an image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackdropKind {
+    Checkerboard,
+    Black,
+    White,
+    Custom,
+}
+
+impl BackdropKind {
+    /// The kind after this one, in the order the key cycles through them.
+    pub fn next(self) -> Self {
+        match self {
+            BackdropKind::Checkerboard => BackdropKind::Black,
+            BackdropKind::Black => BackdropKind::White,
+            BackdropKind::White => BackdropKind::Custom,
+            BackdropKind::Custom => BackdropKind::Checkerboard,
+        }
+    }
+}
+
+impl FromStr for BackdropKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "checkerboard" => Ok(BackdropKind::Checkerboard),
+            "black" => Ok(BackdropKind::Black),
+            "white" => Ok(BackdropKind::White),
+            "custom" => Ok(BackdropKind::Custom),
+            _ => Err(format!("unknown backdrop `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for BackdropKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            BackdropKind::Checkerboard => "checkerboard",
+            BackdropKind::Black => "black",
+            BackdropKind::White => "white",
+            BackdropKind::Custom => "custom",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Fill painted under the image, so that transparent pixels can be told
+/// from white or grey ones.
+pub struct Backdrop {
+    pub kind: BackdropKind,
+    custom: gdk::RGBA,
+    // one light and one dark square in each direction, repeated over the image
+    checkers: Option<cairo::ImageSurface>,
+}
+
+impl Backdrop {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            kind: config.backdrop,
+            custom: config.backdrop_color,
+            checkers: checkers(
+                config.checkerboard_size.max(1) as i32,
+                &config.checkerboard_light,
+                &config.checkerboard_dark,
+            ),
+        }
+    }
+
+    /// Paint the backdrop of an image of `size` drawn with its top left corner at `origin`,
+    /// only where it is visible in `view`. The squares move along with the image.
+    pub fn draw(&self, cr: &cairo::Context, origin: [i32; 2], size: [i32; 2], view: [i32; 2]) {
+        let x0 = origin[0].max(0);
+        let y0 = origin[1].max(0);
+        let x1 = (origin[0] + size[0]).min(view[0]);
+        let y1 = (origin[1] + size[1]).min(view[1]);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        cr.save();
+        cr.rectangle(x0 as f64, y0 as f64, (x1 - x0) as f64, (y1 - y0) as f64);
+        match self.kind {
+            BackdropKind::Checkerboard => match self.checkers.as_ref() {
+                Some(checkers) => {
+                    cr.set_source_surface(checkers, origin[0] as f64, origin[1] as f64);
+                    cr.get_source().set_extend(cairo::Extend::Repeat);
+                }
+                None => cr.set_source_rgb(0.5, 0.5, 0.5),
+            },
+            BackdropKind::Black => cr.set_source_rgb(0.0, 0.0, 0.0),
+            BackdropKind::White => cr.set_source_rgb(1.0, 1.0, 1.0),
+            BackdropKind::Custom => {
+                let color = &self.custom;
+                cr.set_source_rgba(color.red, color.green, color.blue, color.alpha);
+            }
+        }
+        cr.fill();
+        cr.restore();
+    }
+}
+
+/// Tile of the checkerboard with squares of `size` pixels.
+fn checkers(size: i32, light: &gdk::RGBA, dark: &gdk::RGBA) -> Option<cairo::ImageSurface> {
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, size * 2, size * 2).ok()?;
+    let cr = cairo::Context::new(&surface);
+    cr.set_source_rgba(light.red, light.green, light.blue, light.alpha);
+    cr.paint();
+    cr.set_source_rgba(dark.red, dark.green, dark.blue, dark.alpha);
+    cr.rectangle(size as f64, 0.0, size as f64, size as f64);
+    cr.rectangle(0.0, size as f64, size as f64, size as f64);
+    cr.fill();
+    Some(surface)
+}
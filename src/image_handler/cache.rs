@@ -0,0 +1,129 @@
+use gdk_pixbuf::Pixbuf;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+
+/// Memory bounded LRU cache of decoded images.
+pub struct PixbufCache {
+    entries: HashMap<PathBuf, Pixbuf>,
+    // least recently used first
+    order: VecDeque<PathBuf>,
+    bytes: usize,
+    max_bytes: usize,
+    max_images: usize,
+    hits: usize,
+    misses: usize,
+    evictions: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub images: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+impl PixbufCache {
+    pub fn new(max_bytes: usize, max_images: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+            max_images,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Look up `path` and mark it as most recently used.
+    pub fn get(&mut self, path: &PathBuf) -> Option<Pixbuf> {
+        match self.entries.get(path) {
+            Some(pixbuf) => {
+                let pixbuf = pixbuf.clone();
+                self.touch(path);
+                self.hits += 1;
+                Some(pixbuf)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn contains(&self, path: &PathBuf) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Insert a decoded image evicting the least recently used ones to stay within limits.
+    /// Images bigger than the whole budget are not cached.
+    pub fn insert(&mut self, path: PathBuf, pixbuf: Pixbuf) {
+        let size = pixbuf.get_byte_length();
+        if size > self.max_bytes || self.max_images == 0 {
+            return;
+        }
+        if let Some(old) = self.entries.remove(&path) {
+            self.bytes -= old.get_byte_length();
+            self.order.retain(|p| p != &path);
+        }
+        while self.bytes + size > self.max_bytes || self.entries.len() >= self.max_images {
+            let lru = match self.order.pop_front() {
+                Some(lru) => lru,
+                None => break,
+            };
+            if let Some(old) = self.entries.remove(&lru) {
+                self.bytes -= old.get_byte_length();
+                self.evictions += 1;
+            }
+        }
+        self.bytes += size;
+        self.order.push_back(path.clone());
+        self.entries.insert(path, pixbuf);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            images: self.entries.len(),
+            bytes: self.bytes,
+            max_bytes: self.max_bytes,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    /// Drop every image, e.g. once they would be decoded differently.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+
+    fn touch(&mut self, path: &PathBuf) {
+        if let Some(pos) = self.order.iter().position(|p| p == path) {
+            let path = self.order.remove(pos).unwrap();
+            self.order.push_back(path);
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const MB: f64 = 1024.0 * 1024.0;
+        write!(
+            f,
+            "cache: {} images, {:.1}/{:.0} MiB\nhits: {}  misses: {}  evictions: {}",
+            self.images,
+            self.bytes as f64 / MB,
+            self.max_bytes as f64 / MB,
+            self.hits,
+            self.misses,
+            self.evictions,
+        )
+    }
+}
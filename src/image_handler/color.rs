@@ -0,0 +1,343 @@
+use gdk_pixbuf::Pixbuf;
+use lcms2::{
+    CIExyY, CIExyYTRIPLE, ColorSpaceSignature, Flags, Intent, PixelFormat, Profile, ToneCurve,
+    Transform,
+};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const D65: CIExyY = CIExyY {
+    x: 0.3127,
+    y: 0.3290,
+    Y: 1.0,
+};
+
+/// Converts decoded pixels from the colour space of the file to the one of the display.
+///
+/// Files tell their colour space with an embedded ICC profile or, in PNG, with the
+/// cICP, sRGB, gAMA and cHRM chunks. Untagged files and displays without a configured
+/// profile are taken as sRGB. Shared by the decode workers.
+pub struct ColorManager {
+    // ICC profile of the display, sRGB when `None`
+    display: Option<Vec<u8>>,
+    enabled: AtomicBool,
+}
+
+impl ColorManager {
+    /// `display_profile` is an ICC file, an unreadable one falls back to sRGB.
+    pub fn new(display_profile: Option<&Path>, enabled: bool) -> Self {
+        let display = display_profile.and_then(|path| {
+            let icc = fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|icc| match Profile::new_icc(&icc) {
+                    Ok(_) => Ok(icc),
+                    Err(err) => Err(err.to_string()),
+                });
+            match icc {
+                Ok(icc) => Some(icc),
+                Err(err) => {
+                    eprintln!("can't use display profile {:?}: {}", path, err);
+                    None
+                }
+            }
+        });
+        Self {
+            display,
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Show unmanaged colours, images decoded from now on are left as they are.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Convert the 8 bit RGB(A) pixels of `pixbuf`, decoded from `path`, in place.
+    /// The pixbuf must not be shared yet. Returns whether the pixels were converted,
+    /// sRGB images shown on an sRGB display are left as they are.
+    pub fn convert(&self, path: &Path, pixbuf: &Pixbuf) -> Result<bool, String> {
+        if pixbuf.get_bits_per_sample() != 8 {
+            return Ok(false);
+        }
+        // gdk-pixbuf hands the embedded profile of PNG, JPEG, TIFF and WebP files over base64 encoded
+        let icc = pixbuf
+            .get_option("icc-profile")
+            .map(|icc| glib::base64_decode(&icc));
+        let (source, display) = match self.profiles(path, icc.as_deref())? {
+            Some(profiles) => profiles,
+            None => return Ok(false),
+        };
+
+        let channels = pixbuf.get_n_channels() as usize;
+        let format = if pixbuf.get_has_alpha() {
+            PixelFormat::RGBA_8
+        } else {
+            PixelFormat::RGB_8
+        };
+        let transform: Transform<u8, u8> = Transform::new_flags(
+            &source,
+            format,
+            &display,
+            format,
+            Intent::Perceptual,
+            Flags::COPY_ALPHA,
+        )
+        .map_err(|err| err.to_string())?;
+        let width = pixbuf.get_width() as usize;
+        let rowstride = pixbuf.get_rowstride() as usize;
+        // SAFETY: the pixbuf was just decoded and nothing else references its pixels
+        let pixels = unsafe { pixbuf.get_pixels() };
+        for y in 0..pixbuf.get_height() as usize {
+            let start = y * rowstride;
+            if let Some(row) = pixels.get_mut(start..start + width * channels) {
+                transform.transform_in_place(row);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Convert the gamma encoded RGBA float `pixels` of `path`, with the embedded ICC
+    /// profile `icc`, in place. Returns whether the pixels were converted.
+    pub fn convert_float(
+        &self,
+        path: &Path,
+        icc: Option<&[u8]>,
+        pixels: &mut [f32],
+    ) -> Result<bool, String> {
+        let (source, display) = match self.profiles(path, icc)? {
+            Some(profiles) => profiles,
+            None => return Ok(false),
+        };
+        let transform: Transform<[f32; 4], [f32; 4]> = Transform::new_flags(
+            &source,
+            PixelFormat::RGBA_FLT,
+            &display,
+            PixelFormat::RGBA_FLT,
+            Intent::Perceptual,
+            Flags::COPY_ALPHA,
+        )
+        .map_err(|err| err.to_string())?;
+        // converted a few thousand pixels at a time through a small buffer
+        let mut buffer = Vec::new();
+        for chunk in pixels.chunks_mut(4 * 4096) {
+            buffer.clear();
+            buffer.extend(chunk.chunks_exact(4).map(|p| [p[0], p[1], p[2], p[3]]));
+            transform.transform_in_place(&mut buffer);
+            for (pixel, converted) in chunk.chunks_exact_mut(4).zip(&buffer) {
+                pixel.copy_from_slice(converted);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Profiles of the file and of the display, `None` when both are sRGB.
+    fn profiles(
+        &self,
+        path: &Path,
+        icc: Option<&[u8]>,
+    ) -> Result<Option<(Profile, Profile)>, String> {
+        let source = source_profile(path, icc)?;
+        let display = match self.display.as_ref() {
+            Some(icc) => Some(Profile::new_icc(icc).map_err(|err| err.to_string())?),
+            None => None,
+        };
+        if source.is_none() && display.is_none() {
+            return Ok(None);
+        }
+        Ok(Some((
+            source.unwrap_or_else(Profile::new_srgb),
+            display.unwrap_or_else(Profile::new_srgb),
+        )))
+    }
+}
+
+/// Profile describing the pixels of `path` with the embedded profile `icc`, `None` for sRGB.
+/// In PNG, cICP wins over an ICC profile, which wins over sRGB, gAMA and cHRM.
+fn source_profile(path: &Path, icc: Option<&[u8]>) -> Result<Option<Profile>, String> {
+    let png = PngColor::read(path).map_err(|err| err.to_string())?;
+    if let Some([primaries, transfer]) = png.cicp {
+        return cicp_profile(primaries, transfer).map(Some);
+    }
+    if let Some(icc) = icc {
+        let profile = Profile::new_icc(icc).map_err(|err| err.to_string())?;
+        // the loaders expand grayscale to RGB and convert CMYK on their own
+        if profile.color_space() != ColorSpaceSignature::RgbData {
+            return Ok(None);
+        }
+        return Ok(Some(profile));
+    }
+    if png.srgb {
+        return Ok(None);
+    }
+    match (png.gamma, png.chrm) {
+        // 1/2.2 alone is how sRGB images were tagged before the sRGB chunk
+        (Some(gamma), None) if (gamma as i64 - 45455).abs() <= 1000 => Ok(None),
+        (None, None) => Ok(None),
+        (gamma, chrm) => gamma_profile(gamma, chrm).map(Some),
+    }
+}
+
+/// Profile of the coding independent code points of ITU-T H.273,
+/// with the primaries and transfer functions used for still images.
+fn cicp_profile(primaries: u8, transfer: u8) -> Result<Profile, String> {
+    let primaries = match primaries {
+        // BT.709, the same as sRGB
+        1 => xy_triple([0.64, 0.33], [0.30, 0.60], [0.15, 0.06]),
+        // BT.2020
+        9 => xy_triple([0.708, 0.292], [0.170, 0.797], [0.131, 0.046]),
+        // Display P3
+        12 => xy_triple([0.680, 0.320], [0.265, 0.690], [0.150, 0.060]),
+        _ => return Err(format!("unsupported cICP primaries {}", primaries)),
+    };
+    let curve = match transfer {
+        // BT.709 and its relatives
+        1 | 6 | 14 | 15 => ToneCurve::new_parametric(
+            4,
+            &[1.0 / 0.45, 1.0 / 1.099, 0.099 / 1.099, 1.0 / 4.5, 0.081],
+        )
+        .map_err(|err| err.to_string())?,
+        4 => ToneCurve::new(2.2),
+        5 => ToneCurve::new(2.8),
+        8 => ToneCurve::new(1.0),
+        // sRGB
+        13 => srgb_curve()?,
+        _ => return Err(format!("unsupported cICP transfer function {}", transfer)),
+    };
+    Profile::new_rgb(&D65, &primaries, &[&curve, &curve, &curve]).map_err(|err| err.to_string())
+}
+
+/// Profile of the gAMA and cHRM chunks, sRGB fills in whichever is missing.
+fn gamma_profile(gamma: Option<u32>, chrm: Option<[u32; 8]>) -> Result<Profile, String> {
+    let curve = match gamma {
+        // the chunk stores the encoding gamma times 100000
+        Some(gamma) if gamma > 0 => ToneCurve::new(100_000.0 / gamma as f64),
+        _ => srgb_curve()?,
+    };
+    let (white, primaries) = match chrm {
+        Some(chrm) => {
+            let xy = |i: usize| [chrm[i] as f64 / 100_000.0, chrm[i + 1] as f64 / 100_000.0];
+            let [x, y] = xy(0);
+            (CIExyY { x, y, Y: 1.0 }, xy_triple(xy(2), xy(4), xy(6)))
+        }
+        None => (D65, xy_triple([0.64, 0.33], [0.30, 0.60], [0.15, 0.06])),
+    };
+    Profile::new_rgb(&white, &primaries, &[&curve, &curve, &curve]).map_err(|err| err.to_string())
+}
+
+fn srgb_curve() -> Result<ToneCurve, String> {
+    ToneCurve::new_parametric(4, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045])
+        .map_err(|err| err.to_string())
+}
+
+fn xy_triple(red: [f64; 2], green: [f64; 2], blue: [f64; 2]) -> CIExyYTRIPLE {
+    let xy = |[x, y]: [f64; 2]| CIExyY { x, y, Y: 1.0 };
+    CIExyYTRIPLE {
+        Red: xy(red),
+        Green: xy(green),
+        Blue: xy(blue),
+    }
+}
+
+/// Colour chunks of a PNG file, all empty for other files.
+#[derive(Default)]
+struct PngColor {
+    // colour primaries and transfer function
+    cicp: Option<[u8; 2]>,
+    srgb: bool,
+    gamma: Option<u32>,
+    // white point and primaries, x and y times 100000
+    chrm: Option<[u32; 8]>,
+}
+
+impl PngColor {
+    fn read(path: &Path) -> io::Result<Self> {
+        let mut color = Self::default();
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        if file.read_exact(&mut magic).is_err() || &magic != b"\x89PNG\r\n\x1a\n" {
+            return Ok(color);
+        }
+        // the colour chunks come before the image data
+        loop {
+            let mut header = [0u8; 8];
+            if file.read_exact(&mut header).is_err() {
+                return Ok(color);
+            }
+            let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+            let mut data = vec![0u8; len.min(64) as usize];
+            match &header[4..] {
+                b"IDAT" | b"IEND" => return Ok(color),
+                b"cICP" | b"sRGB" | b"gAMA" | b"cHRM" => {
+                    file.read_exact(&mut data)?;
+                    file.seek(SeekFrom::Current(len as i64 - data.len() as i64 + 4))?;
+                }
+                _ => {
+                    file.seek(SeekFrom::Current(len as i64 + 4))?;
+                    continue;
+                }
+            }
+            let be = |i: usize| {
+                data.get(i..i + 4)
+                    .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            };
+            match &header[4..] {
+                b"cICP" if data.len() >= 2 => color.cicp = Some([data[0], data[1]]),
+                b"sRGB" => color.srgb = true,
+                b"gAMA" => color.gamma = be(0),
+                b"cHRM" => {
+                    let mut chrm = [0u32; 8];
+                    for (i, value) in chrm.iter_mut().enumerate() {
+                        *value = be(i * 4).unwrap_or(0);
+                    }
+                    color.chrm = Some(chrm);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // any file that is not a PNG
+    fn untagged_file() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml")
+    }
+
+    #[test]
+    fn srgb_floats_are_left_as_they_are() {
+        let color = ColorManager::new(None, true);
+        let mut pixels = vec![0.25, 0.5, 0.75, 0.5];
+        assert_eq!(
+            color.convert_float(&untagged_file(), None, &mut pixels),
+            Ok(false)
+        );
+        assert_eq!(pixels, [0.25, 0.5, 0.75, 0.5]);
+    }
+
+    #[test]
+    fn wide_gamut_floats_are_converted() {
+        let color = ColorManager::new(None, true);
+        let p3 = cicp_profile(12, 13).unwrap().icc().unwrap();
+        // pure P3 green is outside of sRGB, red and blue go negative
+        let mut pixels = [0.0, 1.0, 0.0, 0.5].repeat(5000);
+        assert_eq!(
+            color.convert_float(&untagged_file(), Some(&p3), &mut pixels),
+            Ok(true)
+        );
+        for pixel in pixels.chunks_exact(4) {
+            assert!(pixel[0] < -0.1, "{:?}", pixel);
+            assert!(pixel[1] > 0.9, "{:?}", pixel);
+            assert_eq!(pixel[3], 0.5);
+        }
+    }
+}
@@ -1,233 +1,1295 @@
-use crate::image_handler::ImageSet;
-use gdk::ScrollDirection;
-use gdk_pixbuf::InterpType;
+use crate::config::Config;
+use crate::image_handler::animation::{DragVelocity, Kinetic, ZoomAnimation};
+use crate::image_handler::backdrop::Backdrop;
+use crate::image_handler::cache::PixbufCache;
+use crate::image_handler::color::ColorManager;
+use crate::image_handler::hdr::{HdrImage, ToneMap, ToneMapped, ToneMapper};
+use crate::image_handler::histogram::{Histogram, Histogrammer};
+use crate::image_handler::info::FileInfo;
+use crate::image_handler::inspector::{self, Sample};
+use crate::image_handler::interpolation::Interpolation;
+use crate::image_handler::loader::{DecodedImage, Loader, PixelData};
+use crate::image_handler::overlay;
+use crate::image_handler::refine::{Refined, Refinement, Refiner};
+use crate::image_handler::slideshow::{self, Slideshow};
+use crate::image_handler::source::SourceImage;
+use crate::image_handler::transition::{Transition, TransitionKind};
+use crate::image_handler::window_level::Window;
+use crate::image_handler::zoom::{parse_percentage, Zoom};
+use crate::image_handler::{Filmstrip, Gallery, ImageSet, MetadataPanel};
+use gdk::prelude::GdkContextExt;
+use gdk::{ScrollDirection, WindowExt};
+use gdk_pixbuf::{InterpType, Pixbuf};
+use glib::{Continue, ObjectExt};
 use gtk;
-use gtk::prelude::{GtkWindowExt, Inhibit, LayoutExt, WidgetExtManual};
-use gtk::{ContainerExt, ImageExt, WidgetExt};
+use gtk::prelude::{DialogExt, EntryExt, GtkWindowExt, Inhibit, WidgetExtManual};
+use gtk::{ContainerExt, LabelExt, SpinnerExt, WidgetExt};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 const LEFT_KEY: u16 = 113;
 const RIGHT_KEY: u16 = 114;
 const ESC: u16 = 9;
+const F12_KEY: u16 = 96;
+const F11_KEY: u16 = 95;
+const F_KEY: u16 = 41;
+const S_KEY: u16 = 39;
+const R_KEY: u16 = 27;
+const SPACE_KEY: u16 = 65;
+const G_KEY: u16 = 42;
+const HOME_KEY: u16 = 110;
+const END_KEY: u16 = 115;
+const RETURN_KEY: u16 = 36;
+const KP_ENTER: u16 = 104;
+const T_KEY: u16 = 28;
+const O_KEY: u16 = 32;
+const B_KEY: u16 = 56;
+const E_KEY: u16 = 26;
+const C_KEY: u16 = 54;
+const X_KEY: u16 = 53;
+const A_KEY: u16 = 38;
+const M_KEY: u16 = 58;
+const D_KEY: u16 = 40;
+const BRACKET_LEFT: u16 = 34;
+const BRACKET_RIGHT: u16 = 35;
+const BACKSLASH: u16 = 51;
+const W_KEY: u16 = 25;
+const V_KEY: u16 = 55;
+const I_KEY: u16 = 31;
+const P_KEY: u16 = 33;
+const PLUS_KEY: u16 = 21;
+const MINUS_KEY: u16 = 20;
+const ZERO_KEY: u16 = 19;
+const KP_ADD: u16 = 86;
+const KP_SUBTRACT: u16 = 82;
+const KP_ZERO: u16 = 90;
+const UP_KEY: u16 = 111;
+const DOWN_KEY: u16 = 116;
+const PAGE_UP: u16 = 112;
+const PAGE_DOWN: u16 = 117;
+const H_KEY: u16 = 43;
+const J_KEY: u16 = 44;
+const K_KEY: u16 = 45;
+const L_KEY: u16 = 46;
+
+// pointer travel in pixels between press and release that still counts as a click
+const CLICK_SLOP: f64 = 3.0;
+
+// exposure and gamma change of one key press for high precision images
+const EXPOSURE_STEP: f32 = 0.5;
+const GAMMA_STEP: f32 = 0.1;
+
+// idle time after the last zoom step before the high quality render starts
+const REFINE_DELAY_MS: u32 = 200;
 
 pub struct Controller {
     pub window: gtk::Window,
-    pub image: Rc<gtk::Image>,
-    pub orig_image: Rc<gtk::Image>,
+    pub canvas: Rc<gtk::DrawingArea>,
+    source: Rc<RefCell<Option<SourceImage>>>,
     pub image_set: Rc<RefCell<ImageSet>>,
     layout: Rc<gtk::Layout>,
-    curr_scale: Rc<RefCell<f32>>,
-    scale_factor: Rc<RefCell<f32>>,
+    spinner: Rc<gtk::Spinner>,
+    debug_label: Rc<gtk::Label>,
+    status_bar: Rc<gtk::Label>,
+    config: Rc<Config>,
+    loader: Rc<Loader>,
+    color: Arc<ColorManager>,
+    // shared with the loader, which renders high precision images with it
+    tone_map: Arc<Mutex<ToneMap>>,
+    tone_mapper: Rc<ToneMapper>,
+    tone_mapped: Option<glib::Receiver<ToneMapped>>,
+    decoded: Option<glib::Receiver<DecodedImage>>,
+    cache: Rc<RefCell<PixbufCache>>,
+    refiner: Rc<Refiner>,
+    refined_views: Option<glib::Receiver<Refinement>>,
+    refined: Rc<RefCell<Option<Refined>>>,
+    zooming: Rc<RefCell<bool>>,
+    interpolation: Rc<RefCell<Interpolation>>,
+    refine_timeout: Rc<RefCell<Option<glib::SourceId>>>,
+    curr_scale: Rc<RefCell<f64>>,
+    // scale the running zoom animation ends at
+    zoom_target: Rc<RefCell<f64>>,
+    zoom_animation: Rc<RefCell<Option<gtk::TickCallbackId>>>,
+    zoom: Rc<Zoom>,
     window_width_height: Rc<RefCell<[i32; 2]>>,
     click_pos: Rc<RefCell<[i32; 2]>>,
     layout_xy: Rc<RefCell<[i32; 2]>>,
     drag: Rc<RefCell<bool>>,
+    drag_velocity: Rc<RefCell<DragVelocity>>,
+    kinetic: Rc<RefCell<Option<gtk::TickCallbackId>>>,
+    fullscreen: Rc<RefCell<bool>>,
+    cursor_timeout: Rc<RefCell<Option<glib::SourceId>>>,
+    slideshow: Rc<RefCell<Slideshow>>,
+    toggle_slideshow: Option<Rc<dyn Fn()>>,
+    transition: Rc<RefCell<Option<Transition>>>,
+    transition_animation: Rc<RefCell<Option<gtk::TickCallbackId>>>,
+    gallery: Rc<RefCell<Gallery>>,
+    filmstrip: Rc<RefCell<Filmstrip>>,
+    metadata_panel: Rc<RefCell<MetadataPanel>>,
+    osd: Rc<RefCell<bool>>,
+    histogrammer: Rc<Histogrammer>,
+    histograms: Option<glib::Receiver<Histogram>>,
+    histogram: Rc<RefCell<Option<Histogram>>>,
+    show_histogram: Rc<RefCell<bool>>,
+    inspector: Rc<RefCell<bool>>,
+    backdrop: Rc<RefCell<Backdrop>>,
+    // pointer position on the canvas, `None` outside of the window
+    pointer: Rc<RefCell<Option<[f64; 2]>>>,
 }
 
 impl Controller {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         window: gtk::Window,
-        image: gtk::Image,
-        image_set: ImageSet,
+        canvas: gtk::DrawingArea,
+        image_set: Rc<RefCell<ImageSet>>,
         layout: gtk::Layout,
+        spinner: gtk::Spinner,
+        debug_label: gtk::Label,
+        status_bar: gtk::Label,
+        gallery: Gallery,
+        filmstrip: Filmstrip,
+        metadata_panel: MetadataPanel,
+        config: Config,
     ) -> Self {
-        let orig_image = Rc::new(image);
-        let image = orig_image.clone();
-        let image_set = Rc::new(RefCell::new(image_set));
+        let canvas = Rc::new(canvas);
+        image_set.borrow_mut().set_shuffle(config.slideshow_shuffle);
         let layout = Rc::new(layout);
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        let color = Arc::new(ColorManager::new(
+            config.display_profile.as_deref(),
+            config.color_management,
+        ));
+        let tone_map = Arc::new(Mutex::new(ToneMap {
+            exposure: config.exposure,
+            gamma: config.display_gamma,
+            operator: config.tone_mapping,
+            window: None,
+            lut: config.lut,
+            keep_window: config.keep_window,
+        }));
+        let auto_window = if config.auto_window {
+            Some([config.auto_window_low, config.auto_window_high])
+        } else {
+            None
+        };
+        let loader = Loader::new(
+            config.decode_workers,
+            sender,
+            Arc::clone(&color),
+            Arc::clone(&tone_map),
+            auto_window,
+        );
+        let (sender, tone_mapped) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        let tone_mapper = ToneMapper::new(sender);
+        let cache = PixbufCache::new(config.cache_max_mb * 1024 * 1024, config.cache_max_images);
+        let (sender, refined_views) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        let refiner = Refiner::new(sender);
+        let zoom = Zoom::new(&config);
+        let slideshow = Slideshow::new(&config);
+        let osd = config.osd;
+        let backdrop = Backdrop::new(&config);
+        let show_histogram = config.histogram;
+        let (sender, histograms) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        let histogrammer = Histogrammer::new(sender);
+        status_bar.set_visible(config.status_bar);
         Self {
             window,
-            image,
-            orig_image,
+            canvas,
+            source: Rc::new(RefCell::new(None)),
             image_set,
             layout,
+            spinner: Rc::new(spinner),
+            debug_label: Rc::new(debug_label),
+            status_bar: Rc::new(status_bar),
+            config: Rc::new(config),
+            loader: Rc::new(loader),
+            color,
+            tone_map,
+            tone_mapper: Rc::new(tone_mapper),
+            tone_mapped: Some(tone_mapped),
+            decoded: Some(receiver),
+            cache: Rc::new(RefCell::new(cache)),
+            refiner: Rc::new(refiner),
+            refined_views: Some(refined_views),
+            refined: Rc::new(RefCell::new(None)),
+            zooming: Rc::new(RefCell::new(false)),
+            interpolation: Rc::new(RefCell::new(Interpolation::Auto)),
+            refine_timeout: Rc::new(RefCell::new(None)),
             curr_scale: Rc::new(RefCell::new(1.0)),
-            scale_factor: Rc::new(RefCell::new(0.10)),
+            zoom_target: Rc::new(RefCell::new(1.0)),
+            zoom_animation: Rc::new(RefCell::new(None)),
+            zoom: Rc::new(zoom),
             window_width_height: Rc::new(RefCell::new([0, 0])),
             click_pos: Rc::new(RefCell::new([0, 0])),
             layout_xy: Rc::new(RefCell::new([0, 0])),
             drag: Rc::new(RefCell::new(false)),
+            drag_velocity: Rc::new(RefCell::new(DragVelocity::default())),
+            kinetic: Rc::new(RefCell::new(None)),
+            fullscreen: Rc::new(RefCell::new(false)),
+            cursor_timeout: Rc::new(RefCell::new(None)),
+            slideshow: Rc::new(RefCell::new(slideshow)),
+            toggle_slideshow: None,
+            transition: Rc::new(RefCell::new(None)),
+            transition_animation: Rc::new(RefCell::new(None)),
+            gallery: Rc::new(RefCell::new(gallery)),
+            filmstrip: Rc::new(RefCell::new(filmstrip)),
+            metadata_panel: Rc::new(RefCell::new(metadata_panel)),
+            osd: Rc::new(RefCell::new(osd)),
+            histogrammer: Rc::new(histogrammer),
+            histograms: Some(histograms),
+            histogram: Rc::new(RefCell::new(None)),
+            show_histogram: Rc::new(RefCell::new(show_histogram)),
+            inspector: Rc::new(RefCell::new(false)),
+            backdrop: Rc::new(RefCell::new(backdrop)),
+            pointer: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Start the slideshow, `interval` replaces the configured one.
+    /// Must be called after `init_events`.
+    pub fn start_slideshow(&self, interval: Option<Duration>) {
+        if let Some(interval) = interval {
+            self.slideshow.borrow_mut().interval = interval;
+        }
+        if let Some(toggle) = self.toggle_slideshow.as_ref() {
+            if !self.slideshow.borrow().is_running() {
+                toggle();
+            }
         }
     }
 
+    /// Request asynchronous decoding of `full_path`,
+    /// the image is shown once the worker pool hands it back.
     pub fn set_from_file(&mut self, full_path: &PathBuf) {
-        let image = Rc::clone(&self.image);
+        debug!("load image path: {:?}", full_path);
+        self.loader.request(full_path.clone());
+        self.spinner.show();
+        self.spinner.start();
+    }
 
-        println!("empty image path: {:?}", full_path);
+    pub fn init_events(&mut self) {
+        // describe the current image in the window title and the status bar
+        let window = self.window.clone();
+        let status_bar = Rc::clone(&self.status_bar);
+        let source = self.source.clone();
+        let image_set = self.image_set.clone();
+        let curr_scale = self.curr_scale.clone();
+        let config = Rc::clone(&self.config);
+        let update_status = Rc::new(move || {
+            let source = source.borrow();
+            let info = match source.as_ref() {
+                Some(source) => &source.info,
+                None => return,
+            };
+            let image_set = image_set.borrow();
+            let expand = |template: &str| {
+                info.expand(
+                    template,
+                    image_set.current_index(),
+                    image_set.len(),
+                    *curr_scale.borrow(),
+                )
+            };
+            window.set_title(&expand(&config.title_template));
+            if status_bar.get_visible() {
+                status_bar.set_text(&expand(&config.status_bar_template));
+            }
+        });
 
-        image.set_from_file(full_path);
-        let pixbuff = match image.get_pixbuf() {
-            Some(pb) => pb,
-            None => return,
-        };
+        // count the levels of the image while the histogram is shown,
+        // also needed whenever the colours of the image change
+        let source = self.source.clone();
+        let curr_scale = self.curr_scale.clone();
+        let layout_xy = self.layout_xy.clone();
+        let wh = self.window_width_height.clone();
+        let histogrammer = Rc::clone(&self.histogrammer);
+        let show_histogram = self.show_histogram.clone();
+        let visible_region = self.config.histogram_visible_region;
+        let request_histogram = Rc::new(move || {
+            if !*show_histogram.borrow() {
+                histogrammer.cancel();
+                return;
+            }
+            let source = source.borrow();
+            let source = match source.as_ref() {
+                Some(source) => source,
+                None => return,
+            };
+            let region = if visible_region {
+                let size = [source.width(), source.height()];
+                let scale = *curr_scale.borrow();
+                match visible_rect(size, scale, *layout_xy.borrow(), *wh.borrow()) {
+                    Some(region) => Some(region),
+                    None => return,
+                }
+            } else {
+                None
+            };
+            histogrammer.request(&source.pixbuf, region);
+        });
 
-        let width = pixbuff.get_width();
-        let height = pixbuff.get_height();
-        *self.window_width_height.borrow_mut() = [width, height];
-        self.window.resize(width, height);
-        let pb = &image.get_pixbuf().unwrap().copy();
-        self.orig_image = Rc::new(gtk::Image::from_pixbuf(pb.as_ref()));
-    }
+        // show a decoded image and reset the view
+        let canvas = Rc::clone(&self.canvas);
+        let source = self.source.clone();
+        let curr_scale_for_show = self.curr_scale.clone();
+        let wh = self.window_width_height.clone();
+        let layout_xy = self.layout_xy.clone();
+        let refiner = Rc::clone(&self.refiner);
+        let refined = self.refined.clone();
+        let window = self.window.clone();
+        let zoom_target = self.zoom_target.clone();
+        let zoom_animation = self.zoom_animation.clone();
+        let kinetic = self.kinetic.clone();
+        let fullscreen = self.fullscreen.clone();
+        let slideshow = self.slideshow.clone();
+        let transition = self.transition.clone();
+        let transition_animation = self.transition_animation.clone();
+        let config = Rc::clone(&self.config);
+        let filmstrip = self.filmstrip.clone();
+        let status_bar = Rc::clone(&self.status_bar);
+        let status = Rc::clone(&update_status);
+        let metadata_panel = self.metadata_panel.clone();
+        let histogram = Rc::clone(&request_histogram);
+        let backdrop = self.backdrop.clone();
+        let tone_mapper = Rc::clone(&self.tone_mapper);
+        let show_image = Rc::new(
+            move |path: &PathBuf,
+                  pixbuff: &Pixbuf,
+                  hdr: Option<Arc<HdrImage>>,
+                  file: FileInfo,
+                  load_time| {
+                let width = pixbuff.get_width();
+                let height = pixbuff.get_height();
+                stop_animation(&zoom_animation);
+                stop_animation(&kinetic);
+                stop_animation(&transition_animation);
+                *transition.borrow_mut() = None;
+                tone_mapper.cancel();
 
-    pub fn init_events(&mut self) {
-        // events on image
+                // the slideshow moves from a snapshot of the image shown until now,
+                // which stays on screen while the next one is being decoded
+                let kind = config.slideshow_transition;
+                let outgoing = match source.borrow_mut().as_mut() {
+                    Some(source)
+                        if kind != TransitionKind::None && slideshow.borrow().is_waiting() =>
+                    {
+                        let background = if *fullscreen.borrow() {
+                            Some(&config.fullscreen_background)
+                        } else {
+                            None
+                        };
+                        let scale = *curr_scale_for_show.borrow();
+                        let origin = *layout_xy.borrow();
+                        let backdrop = backdrop.borrow();
+                        snapshot_view(source, scale, origin, *wh.borrow(), background, &backdrop)
+                    }
+                    _ => None,
+                };
+                if let Some(outgoing) = outgoing {
+                    *transition.borrow_mut() = Some(Transition::new(
+                        kind,
+                        outgoing,
+                        config.slideshow_transition_ms,
+                    ));
+                    let transition = transition.clone();
+                    let transition_animation_done = transition_animation.clone();
+                    let id = canvas.add_tick_callback(move |canvas, frame_clock| {
+                        canvas.queue_draw();
+                        let done = match transition.borrow_mut().as_mut() {
+                            Some(transition) => transition.update(frame_clock.get_frame_time()),
+                            None => true,
+                        };
+                        if !done {
+                            return Continue(true);
+                        }
+                        transition.borrow_mut().take();
+                        transition_animation_done.borrow_mut().take();
+                        Continue(false)
+                    });
+                    *transition_animation.borrow_mut() = Some(id);
+                }
+
+                // 8 bit images are looked at for gray pixels in the background,
+                // to be windowed like 16 bit ones
+                if hdr.is_none() {
+                    if let Some(pixels) = PixelData::from_pixbuf(pixbuff) {
+                        tone_mapper.examine(pixels);
+                    }
+                }
+                // the cache and the source model share the decoded pixbuf
+                *source.borrow_mut() = Some(SourceImage::new(
+                    path,
+                    pixbuff.clone(),
+                    hdr,
+                    file,
+                    load_time,
+                ));
+                refiner.cancel();
+                refiner.build_levels(pixbuff);
+                *refined.borrow_mut() = None;
+                *curr_scale_for_show.borrow_mut() = 1.0;
+                *zoom_target.borrow_mut() = 1.0;
+                slideshow.borrow_mut().shown();
+
+                status();
+                metadata_panel.borrow().show(path);
+                // the panels take their size from the window
+                if !*fullscreen.borrow() {
+                    let status_height = if status_bar.get_visible() {
+                        status_bar.get_allocated_height()
+                    } else {
+                        0
+                    };
+                    window.resize(
+                        width + metadata_panel.borrow().width(),
+                        height + filmstrip.borrow().height() + status_height,
+                    );
+                }
+                filmstrip.borrow().reset();
+                *layout_xy.borrow_mut() = center_xy([width, height], *wh.borrow());
+                histogram();
+                canvas.queue_draw();
+                debug!("new window from pixbuf w/h {:?}", [width, height]);
+            },
+        );
+
+        // cache statistics and the cost of the last decode, shown with <F12>
+        let cache = self.cache.clone();
+        let debug_label = Rc::clone(&self.debug_label);
+        let last_decode = RefCell::new(None);
+        let update_debug = Rc::new(move |decode: Option<(Duration, usize)>| {
+            if decode.is_some() {
+                *last_decode.borrow_mut() = decode;
+            }
+            let mut text = cache.borrow().stats().to_string();
+            if let Some((elapsed, bytes)) = *last_decode.borrow() {
+                text.push_str(&format!(
+                    "\nlast decode: {} ms, {:.1} MiB",
+                    elapsed.as_millis(),
+                    bytes as f64 / (1024.0 * 1024.0)
+                ));
+            }
+            debug_label.set_text(&text);
+        });
+
+        // queue the neighbours of the current image missing in the cache
         let image_set = self.image_set.clone();
-        let image_for_key_event = Rc::clone(&self.image);
-        let orig_image_for_key_event = Rc::clone(&self.orig_image);
-        let curr_scale_for_key_event = self.curr_scale.clone();
-        let scale_factor_for_key_event = self.scale_factor.clone();
-        let layout = Rc::clone(&self.layout);
+        let cache = self.cache.clone();
+        let loader = Rc::clone(&self.loader);
+        let debug = Rc::clone(&update_debug);
+        let n = self.config.prefetch;
+        let prefetch = Rc::new(move || {
+            for path in image_set.borrow().neighbours(n) {
+                if !cache.borrow().contains(&path) {
+                    loader.prefetch(path);
+                }
+            }
+            debug(None);
+        });
+
+        // size and format of the cached images, read along with their pixels
+        let files = Rc::new(RefCell::new(HashMap::<PathBuf, FileInfo>::new()));
+
+        // handle images decoded by the loader
+        let show = Rc::clone(&show_image);
+        let cached_files = Rc::clone(&files);
+        let prefetch_neighbours = Rc::clone(&prefetch);
+        let loader = Rc::clone(&self.loader);
+        let cache = self.cache.clone();
+        let spinner = Rc::clone(&self.spinner);
+        let debug = Rc::clone(&update_debug);
+        let window = self.window.clone();
+        let slideshow = self.slideshow.clone();
+        let color = Arc::clone(&self.color);
+        let tone_map = Arc::clone(&self.tone_map);
+        let decoded = self.decoded.take().expect("init_events called twice");
+        decoded.attach(None, move |decoded| {
+            let current = !decoded.prefetch && loader.is_current(decoded.generation);
+            let pixbuff = match decoded.result {
+                Ok(data) => {
+                    debug!(
+                        "decoded {:?} in {:?}{}",
+                        decoded.path,
+                        decoded.elapsed,
+                        if decoded.color_managed {
+                            ", colors converted"
+                        } else {
+                            ""
+                        }
+                    );
+                    data.to_pixbuf()
+                }
+                Err(err) => {
+                    eprintln!("failed to decode {:?}: {}", decoded.path, err);
+                    if current {
+                        spinner.stop();
+                        spinner.hide();
+                        window.set_title(&format!("{} (failed to load)", decoded.path.display()));
+                        // the slideshow skips the broken image after the usual interval
+                        slideshow.borrow_mut().shown();
+                    }
+                    return Continue(true);
+                }
+            };
+            // stale and prefetched images are still worth keeping, unless they were
+            // decoded before the color management was toggled or are high precision
+            if decoded.color_enabled == color.is_enabled() && decoded.hdr.is_none() {
+                cache
+                    .borrow_mut()
+                    .insert(decoded.path.clone(), pixbuff.clone());
+                let mut files = cached_files.borrow_mut();
+                files.insert(decoded.path.clone(), decoded.file.clone());
+                files.retain(|path, _| cache.borrow().contains(path));
+            }
+            debug(Some((decoded.elapsed, pixbuff.get_byte_length())));
+            if !current {
+                return Continue(true);
+            }
+            spinner.stop();
+            spinner.hide();
+            // the automatic window of the shown image is the one right-drag starts from
+            if let Some(window) = decoded.window {
+                tone_map.lock().unwrap().window = Some(window);
+                debug!("window: {}", window);
+            }
+            show(
+                &decoded.path,
+                &pixbuff,
+                decoded.hdr,
+                decoded.file,
+                Some(decoded.elapsed),
+            );
+            prefetch_neighbours();
+            Continue(true)
+        });
+
+        // render the current view with high quality in the background
+        let source = self.source.clone();
+        let curr_scale = self.curr_scale.clone();
+        let layout_xy = self.layout_xy.clone();
+        let wh = self.window_width_height.clone();
+        let refiner = Rc::clone(&self.refiner);
+        let interpolation = self.interpolation.clone();
+        let config = Rc::clone(&self.config);
+        let histogram = Rc::clone(&request_histogram);
+        let request_refine = Rc::new(move || {
+            // the visible part changed once the view settles
+            if config.histogram_visible_region {
+                histogram();
+            }
+            let scale = *curr_scale.borrow();
+            let interp = match interpolation
+                .borrow()
+                .refine(scale, config.nearest_above_zoom)
+            {
+                Some(interp) => interp,
+                None => return,
+            };
+            if let Some(source) = source.borrow().as_ref() {
+                refiner.request(
+                    &source.tiles,
+                    scale,
+                    *layout_xy.borrow(),
+                    *wh.borrow(),
+                    interp,
+                );
+            }
+        });
+
+        // show high precision images rendered again with new settings, the view is kept
+        let canvas = Rc::clone(&self.canvas);
+        let source = self.source.clone();
+        let tone_mapper = Rc::clone(&self.tone_mapper);
+        let refiner = Rc::clone(&self.refiner);
+        let refined = self.refined.clone();
+        let refine = Rc::clone(&request_refine);
+        let histogram = Rc::clone(&request_histogram);
+        let tone_mapped = self.tone_mapped.take().expect("init_events called twice");
+        tone_mapped.attach(None, move |mapped| {
+            let pixels = match mapped {
+                ToneMapped::Rendered { generation, pixels }
+                    if tone_mapper.is_current(generation) =>
+                {
+                    pixels
+                }
+                ToneMapped::Gray { generation, image } if tone_mapper.is_examined(generation) => {
+                    debug!("grayscale image");
+                    if let Some(source) = source.borrow_mut().as_mut() {
+                        source.hdr = Some(image);
+                    }
+                    return Continue(true);
+                }
+                _ => return Continue(true),
+            };
+            let pixbuf = pixels.to_pixbuf();
+            if let Some(source) = source.borrow_mut().as_mut() {
+                source.set_pixbuf(pixbuf.clone());
+            }
+            refiner.cancel();
+            refiner.build_levels(&pixbuf);
+            *refined.borrow_mut() = None;
+            refine();
+            histogram();
+            canvas.queue_draw();
+            Continue(true)
+        });
+
+        // change the tone mapping and render the current image again if it is high precision
+        let source = self.source.clone();
+        let tone_map = Arc::clone(&self.tone_map);
+        let tone_mapper = Rc::clone(&self.tone_mapper);
+        let adjust_tone_map = Rc::new(move |change: &dyn Fn(&mut ToneMap)| {
+            let map = {
+                let mut map = tone_map.lock().unwrap();
+                change(&mut map);
+                *map
+            };
+            debug!("tone mapping: {}", map);
+            let hdr = source
+                .borrow()
+                .as_ref()
+                .and_then(|source| source.hdr.clone());
+            if let Some(hdr) = hdr {
+                tone_mapper.request(hdr, map);
+            }
+        });
+
+        // set the zoom and position the image in the center of the layout
+        let canvas = Rc::clone(&self.canvas);
+        let source = self.source.clone();
+        let curr_scale = self.curr_scale.clone();
+        let zoom = Rc::clone(&self.zoom);
         let wh = self.window_width_height.clone();
         let layout_xy = self.layout_xy.clone();
+        let zooming = self.zooming.clone();
+        let refine_timeout = self.refine_timeout.clone();
+        let refine = Rc::clone(&request_refine);
+        let status = Rc::clone(&update_status);
+        let set_scale = Rc::new(move |new_scale: f64| {
+            let size = match source.borrow().as_ref() {
+                Some(source) => source.scaled_size(zoom.clamp(new_scale)),
+                None => return,
+            };
+            // only visible tiles are rendered, so the scaled size is not limited by memory
+            *curr_scale.borrow_mut() = zoom.clamp(new_scale);
+            *layout_xy.borrow_mut() = center_xy(size, *wh.borrow());
+            status();
+
+            // draw fast until the user stops zooming, then refine
+            *zooming.borrow_mut() = true;
+            if let Some(id) = refine_timeout.borrow_mut().take() {
+                glib::source_remove(id);
+            }
+            let zooming = zooming.clone();
+            let refine_timeout_done = refine_timeout.clone();
+            let canvas_to_refine = Rc::clone(&canvas);
+            let refine = Rc::clone(&refine);
+            let id = glib::timeout_add_local(REFINE_DELAY_MS, move || {
+                refine_timeout_done.borrow_mut().take();
+                *zooming.borrow_mut() = false;
+                canvas_to_refine.queue_draw();
+                refine();
+                Continue(false)
+            });
+            *refine_timeout.borrow_mut() = Some(id);
+            canvas.queue_draw();
+        });
+
+        // zoom to a new scale, animated over a few frames of the frame clock when enabled
+        let canvas = Rc::clone(&self.canvas);
+        let curr_scale = self.curr_scale.clone();
+        let zoom = Rc::clone(&self.zoom);
+        let zoom_target = self.zoom_target.clone();
+        let zoom_animation = self.zoom_animation.clone();
+        let kinetic = self.kinetic.clone();
+        let animated_zoom = self.config.animated_zoom;
+        let zoom_to = Rc::new(move |new_scale: f64| {
+            let new_scale = zoom.clamp(new_scale);
+            *zoom_target.borrow_mut() = new_scale;
+            stop_animation(&zoom_animation);
+            stop_animation(&kinetic);
+            if !animated_zoom {
+                set_scale(new_scale);
+                return;
+            }
+            // a zoom during the animation continues from the scale shown right now
+            let animation = RefCell::new(ZoomAnimation::new(*curr_scale.borrow(), new_scale));
+            let set_scale = Rc::clone(&set_scale);
+            let zoom_animation_done = zoom_animation.clone();
+            let id = canvas.add_tick_callback(move |_canvas, frame_clock| {
+                let (scale, done) = animation
+                    .borrow_mut()
+                    .scale_at(frame_clock.get_frame_time());
+                set_scale(scale);
+                if done {
+                    zoom_animation_done.borrow_mut().take();
+                    return Continue(false);
+                }
+                Continue(true)
+            });
+            *zoom_animation.borrow_mut() = Some(id);
+        });
+
+        // move the view over a zoomed image, keeping image inside window border
+        let canvas = Rc::clone(&self.canvas);
+        let source = self.source.clone();
+        let curr_scale = self.curr_scale.clone();
+        let wh = self.window_width_height.clone();
+        let layout_xy = self.layout_xy.clone();
+        let move_view = Rc::new(move |dx: i32, dy: i32| -> bool {
+            let size = match source.borrow().as_ref() {
+                Some(source) => source.scaled_size(*curr_scale.borrow()),
+                None => return false,
+            };
+            let [x, y] = *layout_xy.borrow();
+            let xy = clamp_xy([x - dx, y - dy], size, *wh.borrow());
+            if xy == [x, y] {
+                return false;
+            }
+            *layout_xy.borrow_mut() = xy;
+            canvas.queue_draw();
+            true
+        });
+        let move_by = Rc::clone(&move_view);
+        let refine = Rc::clone(&request_refine);
+        let pan_by = Rc::new(move |dx: i32, dy: i32| {
+            if move_by(dx, dy) {
+                refine();
+            }
+        });
+
+        // show an image of the set, decode it first unless it is cached
+        let cache = self.cache.clone();
+        let loader = Rc::clone(&self.loader);
+        let spinner = Rc::clone(&self.spinner);
+        let debug = Rc::clone(&update_debug);
+        let tone_map = Arc::clone(&self.tone_map);
+        let opened = Rc::new(RefCell::new(None::<PathBuf>));
+        let open_image = Rc::new(move |path: PathBuf| {
+            // a window belongs to the image it was set on, it stays when the same image
+            // is opened again, e.g. after toggling the color management
+            if opened.borrow().as_ref() != Some(&path) {
+                tone_map.lock().unwrap().next_image();
+                *opened.borrow_mut() = Some(path.clone());
+            }
+            let cached = cache.borrow_mut().get(&path);
+            match cached {
+                Some(pixbuff) => {
+                    // cancel whatever is still being decoded for the previous image
+                    loader.cancel();
+                    spinner.stop();
+                    spinner.hide();
+                    let file = files.borrow().get(&path).cloned().unwrap_or_default();
+                    show_image(&path, &pixbuff, None, file, None);
+                    prefetch();
+                }
+                None => {
+                    // a newer request cancels the one still being decoded
+                    loader.request(path);
+                    spinner.show();
+                    spinner.start();
+                    debug(None);
+                }
+            }
+        });
+
+        // start or stop the slideshow, the timer advances once the shown image is due
+        let image_set = self.image_set.clone();
+        let slideshow = self.slideshow.clone();
+        let open = Rc::clone(&open_image);
+        let toggle_slideshow = Rc::new(move || {
+            if slideshow.borrow().is_running() {
+                slideshow.borrow_mut().stop();
+                debug!("slideshow: stopped");
+                return;
+            }
+            let image_set = image_set.clone();
+            let slideshow_tick = slideshow.clone();
+            let open = Rc::clone(&open);
+            let id = glib::timeout_add_local(slideshow::TICK_MS, move || {
+                if !slideshow_tick.borrow().is_due() {
+                    return Continue(true);
+                }
+                let looped = slideshow_tick.borrow().looped;
+                let path = image_set.borrow_mut().advance(looped);
+                match path {
+                    Some(path) => {
+                        slideshow_tick.borrow_mut().advanced();
+                        open(path);
+                        Continue(true)
+                    }
+                    None => {
+                        debug!("slideshow: end");
+                        slideshow_tick.borrow_mut().stopped();
+                        Continue(false)
+                    }
+                }
+            });
+            slideshow.borrow_mut().start(id);
+            debug!("slideshow: every {:?}", slideshow.borrow().interval);
+        });
+        self.toggle_slideshow = Some(toggle_slideshow.clone());
+
+        // open the image picked in the gallery
+        let image_set = self.image_set.clone();
+        let gallery = self.gallery.clone();
+        let open = Rc::clone(&open_image);
+        let open_at = Rc::new(move |index: usize| {
+            gallery.borrow().hide();
+            let path = image_set.borrow_mut().go_to(index);
+            if let Some(path) = path {
+                open(path);
+            }
+        });
+        self.gallery.borrow_mut().init_events(open_at.clone());
+        self.filmstrip.borrow_mut().init_events(open_at.clone());
+        self.metadata_panel.borrow_mut().init_events();
+
+        // events on image
+        let image_set = self.image_set.clone();
+        let debug_label = Rc::clone(&self.debug_label);
+        let canvas = Rc::clone(&self.canvas);
+        let interpolation = self.interpolation.clone();
+        let refined = self.refined.clone();
+        let refine = Rc::clone(&request_refine);
+        let pan_by = Rc::clone(&pan_by);
+        let zoom_target = self.zoom_target.clone();
+        let zoom = Rc::clone(&self.zoom);
+        let zoom_to_level = Rc::clone(&zoom_to);
+        let wh = self.window_width_height.clone();
+        let pan_step = self.config.pan_step;
+        let fullscreen = self.fullscreen.clone();
+        let slideshow = self.slideshow.clone();
+        let gallery = self.gallery.clone();
+        let filmstrip = self.filmstrip.clone();
+        let osd = self.osd.clone();
+        let status_bar = Rc::clone(&self.status_bar);
+        let status = Rc::clone(&update_status);
+        let metadata_panel = self.metadata_panel.clone();
+        let show_histogram = self.show_histogram.clone();
+        let histogram = Rc::clone(&request_histogram);
+        let inspector = self.inspector.clone();
+        let backdrop = self.backdrop.clone();
+        let color = Arc::clone(&self.color);
+        let cache = self.cache.clone();
+        let tone = Rc::clone(&adjust_tone_map);
+        let source = self.source.clone();
+        let percentiles = [self.config.auto_window_low, self.config.auto_window_high];
 
         // handle events
         self.window
             .connect_key_press_event(move |window, event_key| {
-                match event_key.get_keycode() {
-                    Some(LEFT_KEY) => {
-                        let mut prev_image = image_set.borrow_mut();
-                        let prev_image: PathBuf = match prev_image.prev() {
-                            Some(pi) => pi,
-                            None => return Inhibit::default(),
-                        };
-                        let image = &image_for_key_event;
-                        image.set_from_file(&prev_image);
-                        let pixbuff = image.get_pixbuf().unwrap();
-                        let width = pixbuff.get_width();
-                        let height = pixbuff.get_height();
-
-                        let orig_image = &orig_image_for_key_event;
-                        orig_image.set_from_pixbuf(pixbuff.copy().as_ref());
-                        *curr_scale_for_key_event.borrow_mut() = 1.0;
-                        *scale_factor_for_key_event.borrow_mut() = 0.10;
-
-                        window.set_title(&prev_image.to_str().unwrap());
-                        // window.set_size_request(width, height);
-                        window.resize(width, height);
-                        layout.set_child_x(image_for_key_event.clone().as_ref(), 0);
-                        layout.set_child_y(image_for_key_event.clone().as_ref(), 0);
-                        layout.set_size(0, 0);
-                        *wh.borrow_mut() = [width, height];
-                        *layout_xy.borrow_mut() = [0, 0];
-                    }
-                    Some(RIGHT_KEY) => {
-                        let mut next_image = image_set.borrow_mut();
-                        let next_image = match next_image.next() {
-                            Some(ni) => ni,
-                            None => return Inhibit::default(),
-                        };
-                        let image = &image_for_key_event;
-                        image.set_from_file(&next_image);
-                        let pixbuff = image.get_pixbuf().unwrap();
-                        let width = pixbuff.get_width();
-                        let height = pixbuff.get_height();
-
-                        let orig_image = &orig_image_for_key_event;
-                        orig_image.set_from_pixbuf(pixbuff.copy().as_ref());
-                        *curr_scale_for_key_event.borrow_mut() = 1.0;
-                        *scale_factor_for_key_event.borrow_mut() = 0.10;
-
-                        window.set_title(&next_image.to_str().unwrap());
-                        window.resize(width, height);
-                        layout.set_child_x(image_for_key_event.clone().as_ref(), 0);
-                        layout.set_child_y(image_for_key_event.clone().as_ref(), 0);
-                        layout.set_size(0, 0);
-                        *wh.borrow_mut() = [width, height];
-                        *layout_xy.borrow_mut() = [0, 0];
-                        println!("\nNEW window from pb w/h {:?}", [width, height]);
-                    }
-                    Some(ESC) => gtk::main_quit(),
-                    _ => (),
+                // typing in the metadata filter, <Esc> leaves it
+                if let Some(focus) = window.get_focus() {
+                    if focus.is::<gtk::Entry>() {
+                        if event_key.get_keycode() != Some(ESC) {
+                            return Inhibit(false);
+                        }
+                        window.set_focus(None::<&gtk::Widget>);
+                        return Inhibit(true);
+                    }
+                }
+                if gallery.borrow().is_visible() {
+                    let gallery = gallery.borrow();
+                    let columns = gallery.columns();
+                    let page = gallery.page_rows() * columns;
+                    match event_key.get_keycode() {
+                        Some(LEFT_KEY) => gallery.move_selection(-1),
+                        Some(RIGHT_KEY) => gallery.move_selection(1),
+                        Some(UP_KEY) => gallery.move_selection(-columns),
+                        Some(DOWN_KEY) => gallery.move_selection(columns),
+                        Some(PAGE_UP) => gallery.move_selection(-page),
+                        Some(PAGE_DOWN) => gallery.move_selection(page),
+                        Some(HOME_KEY) => gallery.select(0),
+                        Some(END_KEY) => gallery.select(usize::MAX),
+                        Some(RETURN_KEY) | Some(KP_ENTER) => {
+                            let selected = gallery.selected();
+                            drop(gallery);
+                            open_at(selected);
+                        }
+                        Some(G_KEY) | Some(ESC) => gallery.hide(),
+                        _ => (),
+                    }
+                    return Inhibit(true);
+                }
+                let scale = *zoom_target.borrow();
+                let shift = event_key
+                    .get_state()
+                    .contains(gdk::ModifierType::SHIFT_MASK);
+                let control = event_key
+                    .get_state()
+                    .contains(gdk::ModifierType::CONTROL_MASK);
+                // a page is most of the view, so that some context stays visible
+                let [page_x, page_y] = {
+                    let [w, h] = *wh.borrow();
+                    [w * 9 / 10, h * 9 / 10]
                 };
+                let path = match event_key.get_keycode() {
+                    // arrows move between images, with shift they pan like hjkl
+                    Some(LEFT_KEY) if shift => {
+                        pan_by(-pan_step, 0);
+                        None
+                    }
+                    Some(RIGHT_KEY) if shift => {
+                        pan_by(pan_step, 0);
+                        None
+                    }
+                    Some(D_KEY) => {
+                        let visible = !*show_histogram.borrow();
+                        *show_histogram.borrow_mut() = visible;
+                        histogram();
+                        canvas.queue_draw();
+                        None
+                    }
+                    Some(H_KEY) => {
+                        pan_by(-pan_step, 0);
+                        None
+                    }
+                    Some(L_KEY) => {
+                        pan_by(pan_step, 0);
+                        None
+                    }
+                    Some(UP_KEY) | Some(K_KEY) => {
+                        pan_by(0, -pan_step);
+                        None
+                    }
+                    Some(DOWN_KEY) | Some(J_KEY) => {
+                        pan_by(0, pan_step);
+                        None
+                    }
+                    Some(PAGE_UP) if shift => {
+                        pan_by(-page_x, 0);
+                        None
+                    }
+                    Some(PAGE_DOWN) if shift => {
+                        pan_by(page_x, 0);
+                        None
+                    }
+                    Some(PAGE_UP) => {
+                        pan_by(0, -page_y);
+                        None
+                    }
+                    Some(PAGE_DOWN) => {
+                        pan_by(0, page_y);
+                        None
+                    }
+                    Some(LEFT_KEY) => image_set.borrow_mut().prev(),
+                    Some(RIGHT_KEY) => image_set.borrow_mut().next(),
+                    Some(F12_KEY) => {
+                        debug_label.set_visible(!debug_label.get_visible());
+                        None
+                    }
+                    Some(I_KEY) => {
+                        let next = interpolation.borrow().next();
+                        *interpolation.borrow_mut() = next;
+                        debug!("interpolation: {}", next);
+                        *refined.borrow_mut() = None;
+                        refine();
+                        canvas.queue_draw();
+                        None
+                    }
+                    Some(PLUS_KEY) | Some(KP_ADD) => {
+                        zoom_to_level(zoom.preset_in(scale));
+                        None
+                    }
+                    Some(MINUS_KEY) | Some(KP_SUBTRACT) => {
+                        zoom_to_level(zoom.preset_out(scale));
+                        None
+                    }
+                    Some(ZERO_KEY) | Some(KP_ZERO) => {
+                        zoom_to_level(1.0);
+                        None
+                    }
+                    Some(P_KEY) => {
+                        if let Some(new_scale) = prompt_zoom(window, scale) {
+                            zoom_to_level(new_scale);
+                        }
+                        None
+                    }
+                    Some(F11_KEY) | Some(F_KEY) => {
+                        if *fullscreen.borrow() {
+                            window.unfullscreen();
+                        } else {
+                            window.fullscreen();
+                        }
+                        None
+                    }
+                    Some(S_KEY) => {
+                        toggle_slideshow();
+                        None
+                    }
+                    Some(G_KEY) => {
+                        slideshow.borrow_mut().stop();
+                        gallery.borrow().show();
+                        None
+                    }
+                    Some(T_KEY) => {
+                        let filmstrip = filmstrip.borrow();
+                        filmstrip.set_visible(!filmstrip.is_visible());
+                        None
+                    }
+                    Some(B_KEY) => {
+                        status_bar.set_visible(!status_bar.get_visible());
+                        status();
+                        None
+                    }
+                    Some(E_KEY) => {
+                        let metadata_panel = metadata_panel.borrow();
+                        metadata_panel.set_visible(!metadata_panel.is_visible());
+                        None
+                    }
+                    Some(C_KEY) if control && metadata_panel.borrow().is_visible() => {
+                        metadata_panel.borrow().copy_selection();
+                        None
+                    }
+                    Some(X_KEY) => {
+                        let inspecting = !*inspector.borrow();
+                        *inspector.borrow_mut() = inspecting;
+                        set_crosshair(&canvas, inspecting);
+                        canvas.queue_draw();
+                        None
+                    }
+                    Some(A_KEY) => {
+                        let mut backdrop = backdrop.borrow_mut();
+                        backdrop.kind = backdrop.kind.next();
+                        debug!("backdrop: {}", backdrop.kind);
+                        canvas.queue_draw();
+                        None
+                    }
+                    Some(BRACKET_LEFT) if shift => {
+                        tone(&|map| map.gamma = (map.gamma - GAMMA_STEP).max(GAMMA_STEP));
+                        None
+                    }
+                    Some(BRACKET_RIGHT) if shift => {
+                        tone(&|map| map.gamma += GAMMA_STEP);
+                        None
+                    }
+                    Some(BRACKET_LEFT) => {
+                        tone(&|map| map.exposure -= EXPOSURE_STEP);
+                        None
+                    }
+                    Some(BRACKET_RIGHT) => {
+                        tone(&|map| map.exposure += EXPOSURE_STEP);
+                        None
+                    }
+                    Some(BACKSLASH) => {
+                        tone(&|map| map.operator = map.operator.next());
+                        None
+                    }
+                    Some(W_KEY) if shift => {
+                        tone(&|map| map.window = None);
+                        None
+                    }
+                    Some(W_KEY) => {
+                        let window = source
+                            .borrow()
+                            .as_ref()
+                            .and_then(|source| source.hdr.clone())
+                            .filter(|hdr| hdr.grayscale)
+                            .map(|hdr| hdr.auto_window(percentiles));
+                        if let Some(window) = window {
+                            tone(&|map| map.window = Some(window));
+                        }
+                        None
+                    }
+                    Some(V_KEY) => {
+                        tone(&|map| map.lut = map.lut.next());
+                        None
+                    }
+                    Some(M_KEY) => {
+                        let managed = !color.is_enabled();
+                        color.set_enabled(managed);
+                        debug!("color management: {}", managed);
+                        // decode the current image again, the cached ones as well once shown
+                        cache.borrow_mut().clear();
+                        let image_set = image_set.borrow();
+                        image_set.get(image_set.current_index())
+                    }
+                    Some(O_KEY) => {
+                        let visible = !*osd.borrow();
+                        *osd.borrow_mut() = visible;
+                        canvas.queue_draw();
+                        None
+                    }
+                    Some(SPACE_KEY) if slideshow.borrow().is_running() => {
+                        slideshow.borrow_mut().toggle_pause();
+                        debug!("slideshow: paused {}", slideshow.borrow().is_paused());
+                        None
+                    }
+                    Some(R_KEY) => {
+                        let shuffle = !image_set.borrow().is_shuffled();
+                        image_set.borrow_mut().set_shuffle(shuffle);
+                        debug!("slideshow: shuffle {}", shuffle);
+                        None
+                    }
+                    // leave the slideshow and fullscreen before quitting
+                    Some(ESC) if *fullscreen.borrow() || slideshow.borrow().is_running() => {
+                        slideshow.borrow_mut().stop();
+                        window.unfullscreen();
+                        None
+                    }
+                    Some(ESC) => {
+                        gtk::main_quit();
+                        None
+                    }
+                    _ => None,
+                };
+                if let Some(path) = path {
+                    open_image(path);
+                }
                 Inhibit::default()
             });
 
-        let image_for_scroll_event = Rc::clone(&self.image);
-        let orig_image_for_scroll_event = Rc::clone(&self.orig_image);
-        let curr_scale_for_scroll_event = self.curr_scale.clone();
-        let scale_factor_for_scroll_event = self.scale_factor.clone();
-        let layout = Rc::clone(&self.layout);
+        // draw the high quality render of the view when there is one, the visible tiles otherwise
+        let source = self.source.clone();
+        let curr_scale = self.curr_scale.clone();
         let layout_xy = self.layout_xy.clone();
+        let wh = self.window_width_height.clone();
+        let refined = self.refined.clone();
+        let zooming = self.zooming.clone();
+        let interpolation = self.interpolation.clone();
+        let config = Rc::clone(&self.config);
+        let fullscreen = self.fullscreen.clone();
+        let transition = self.transition.clone();
+        let image_set = self.image_set.clone();
+        let osd = self.osd.clone();
+        let show_histogram = self.show_histogram.clone();
+        let histogram = self.histogram.clone();
+        let inspector = self.inspector.clone();
+        let pointer = self.pointer.clone();
+        let backdrop = self.backdrop.clone();
+        self.canvas.connect_draw(move |_canvas, cr| {
+            let scale = *curr_scale.borrow();
+            let origin = *layout_xy.borrow();
+            let view = *wh.borrow();
+            if *fullscreen.borrow() {
+                let bg = &config.fullscreen_background;
+                cr.set_source_rgba(bg.red, bg.green, bg.blue, bg.alpha);
+                cr.paint();
+            }
+            let mut source = source.borrow_mut();
+            let source = match source.as_mut() {
+                Some(source) => source,
+                None => return Inhibit(false),
+            };
+            let transition = transition.borrow();
+            if let Some(transition) = transition.as_ref() {
+                transition.push(cr, view);
+            }
+            let size = source.scaled_size(scale);
+            backdrop.borrow().draw(cr, origin, size, view);
 
-        // scale image on scroll event
-        // and position it properly in the center of the layout
-        self.window.add_events(gdk::EventMask::SCROLL_MASK);
-        self.window
-            .connect_scroll_event(move |window, scroll_event| {
-                match scroll_event.get_direction() {
-                    ScrollDirection::Up => {
-                        let image = &orig_image_for_scroll_event;
-                        let mut curr_scale = curr_scale_for_scroll_event.borrow_mut();
-                        *curr_scale = *curr_scale + *scale_factor_for_scroll_event.borrow();
-
-                        if *curr_scale >= 4.0 {
-                            *curr_scale = *curr_scale - *scale_factor_for_scroll_event.borrow();
-                            return Inhibit::default();
-                        }
+            match refined.borrow().as_ref() {
+                Some(refined) if refined.matches(scale, origin, view) => {
+                    let x = (origin[0] + refined.offset[0]) as f64;
+                    let y = (origin[1] + refined.offset[1]) as f64;
+                    cr.set_source_pixbuf(&refined.pixbuf, x, y);
+                    cr.paint();
+                }
+                _ => {
+                    let interp = interpolation.borrow().tiles(
+                        scale,
+                        *zooming.borrow(),
+                        config.nearest_above_zoom,
+                    );
+                    source.tiles.draw(cr, scale, origin, view, interp);
+                }
+            }
 
-                        let pixbuff = match (&*image).get_pixbuf() {
-                            Some(pb) => pb,
-                            None => return Inhibit::default(),
-                        };
-                        let width = pixbuff.get_width();
-                        let height = pixbuff.get_height();
-
-                        let dest_width = (width as f64 * *curr_scale as f64).round() as i32;
-                        let dest_height = (height as f64 * *curr_scale as f64).round() as i32;
-                        let rescaled_pixbuff =
-                            &pixbuff.scale_simple(dest_width, dest_height, InterpType::Bilinear);
-                        let image = &image_for_scroll_event;
-                        image.set_from_pixbuf(rescaled_pixbuff.as_ref());
-                        // window.set_size_request(width, height);
-
-                        // translate image to the center of the view
-                        let w_width = window.get_allocated_width();
-                        let w_height = window.get_allocated_height();
-                        let xwc = (w_width as f32 / 2.0) as i32;
-                        let ywc = (w_height as f32 / 2.0) as i32;
-                        let xic = (dest_width as f32 / 2.0) as i32;
-                        let yic = (dest_height as f32 / 2.0) as i32;
-                        layout.set_child_x(image.clone().as_ref(), xwc - xic);
-                        layout.set_child_y(image.clone().as_ref(), ywc - yic);
-                        *layout_xy.borrow_mut() = [xwc - xic, ywc - yic];
-                    }
-                    ScrollDirection::Down => {
-                        let image = &orig_image_for_scroll_event;
-                        let mut curr_scale = curr_scale_for_scroll_event.borrow_mut();
-                        *curr_scale = *curr_scale - *scale_factor_for_scroll_event.borrow();
-
-                        let pixbuff = match (&*image).get_pixbuf() {
-                            Some(pb) => pb,
-                            None => return Inhibit::default(),
-                        };
-                        let width = pixbuff.get_width();
-                        let height = pixbuff.get_height();
+            // outline the image pixels once they are big enough to tell apart
+            if config.pixel_grid && scale >= config.pixel_grid_min_zoom {
+                overlay::draw_pixel_grid(cr, scale, origin, size, view);
+            }
+            if config.scroll_indicators {
+                overlay::draw_scroll_indicators(cr, origin, size, view);
+            }
+            if let Some(transition) = transition.as_ref() {
+                transition.pop(cr, view);
+            }
+            if *osd.borrow() {
+                let image_set = image_set.borrow();
+                let text = source.info.expand(
+                    &config.osd_template,
+                    image_set.current_index(),
+                    image_set.len(),
+                    scale,
+                );
+                overlay::draw_osd(cr, &text, config.osd_position, view);
+            }
+            if *show_histogram.borrow() {
+                if let Some(histogram) = histogram.borrow().as_ref() {
+                    overlay::draw_histogram(cr, histogram, config.histogram_position, view);
+                }
+            }
+            if *inspector.borrow() {
+                let size = [source.width(), source.height()];
+                let sample = pointer
+                    .borrow()
+                    .and_then(|pos| inspector::image_xy(pos, scale, origin, size, view))
+                    .and_then(|[x, y]| Sample::at(&source.pixbuf, x, y))
+                    .map(|sample| sample.with_original(source.hdr.as_deref()));
+                if let Some(sample) = sample {
+                    overlay::draw_osd(cr, &sample.describe(), config.inspector_position, view);
+                }
+            }
+            Inhibit(false)
+        });
+
+        // swap in high quality renders and mipmap levels finished in the background
+        let canvas = Rc::clone(&self.canvas);
+        let refiner = Rc::clone(&self.refiner);
+        let refined = self.refined.clone();
+        let source = self.source.clone();
+        let refined_views = self.refined_views.take().expect("init_events called twice");
+        refined_views.attach(None, move |refinement| {
+            match refinement {
+                Refinement::View(view) if refiner.is_current(view.generation) => {
+                    *refined.borrow_mut() = Some(Refined::new(view));
+                }
+                Refinement::Levels { generation, levels }
+                    if refiner.are_levels_current(generation) =>
+                {
+                    if let Some(source) = source.borrow_mut().as_mut() {
+                        source
+                            .tiles
+                            .set_levels(levels.iter().map(PixelData::to_pixbuf).collect());
+                    }
+                }
+                _ => return Continue(true),
+            }
+            canvas.queue_draw();
+            Continue(true)
+        });
 
-                        let dest_width = (width as f64 * *curr_scale as f64).round() as i32;
-                        let dest_height = (height as f64 * *curr_scale as f64).round() as i32;
+        // show histograms counted in the background
+        let canvas = Rc::clone(&self.canvas);
+        let histogrammer = Rc::clone(&self.histogrammer);
+        let histogram = self.histogram.clone();
+        let histograms = self.histograms.take().expect("init_events called twice");
+        histograms.attach(None, move |counted| {
+            if histogrammer.is_current(counted.generation) {
+                *histogram.borrow_mut() = Some(counted);
+                canvas.queue_draw();
+            }
+            Continue(true)
+        });
 
-                        if dest_height <= 20 || dest_width <= 20 {
-                            *curr_scale = *curr_scale + *scale_factor_for_scroll_event.borrow();
-                            return Inhibit::default();
-                        }
+        let zoom_target = self.zoom_target.clone();
+        let zoom = Rc::clone(&self.zoom);
 
-                        let rescaled_pixbuff =
-                            &pixbuff.scale_simple(dest_width, dest_height, InterpType::Bilinear);
-                        let image = &image_for_scroll_event;
-                        image.set_from_pixbuf(rescaled_pixbuff.as_ref());
-                        // translate image to the center of the view
-                        let w_width = window.get_allocated_width();
-                        let w_height = window.get_allocated_height();
-                        let xwc = (w_width as f32 / 2.0) as i32;
-                        let ywc = (w_height as f32 / 2.0) as i32;
-                        let xic = (dest_width as f32 / 2.0) as i32;
-                        let yic = (dest_height as f32 / 2.0) as i32;
-                        layout.set_child_x(image.clone().as_ref(), xwc - xic);
-                        layout.set_child_y(image.clone().as_ref(), ywc - yic);
-                        *layout_xy.borrow_mut() = [xwc - xic, ywc - yic];
-                    }
+        // scale image on scroll event, steps add up while the zoom is animated
+        self.window.add_events(gdk::EventMask::SCROLL_MASK);
+        self.window
+            .connect_scroll_event(move |_window, scroll_event| {
+                let scale = *zoom_target.borrow();
+                match scroll_event.get_direction() {
+                    ScrollDirection::Up => zoom_to(zoom.step_in(scale)),
+                    ScrollDirection::Down => zoom_to(zoom.step_out(scale)),
                     _ => (),
                 };
                 Inhibit::default()
@@ -236,128 +1298,400 @@ impl Controller {
         self.window.add_events(
             gdk::EventMask::POINTER_MOTION_MASK
                 | gdk::EventMask::BUTTON_PRESS_MASK
-                | gdk::EventMask::BUTTON_RELEASE_MASK,
+                | gdk::EventMask::BUTTON_RELEASE_MASK
+                | gdk::EventMask::LEAVE_NOTIFY_MASK,
         );
+        // where the last button went down, a release close to it is a click
+        let press_root = Rc::new(RefCell::new((0.0, 0.0)));
+        // where the right button went down on a grayscale image and its window then, while held
+        let leveling = Rc::new(RefCell::new(None::<((f64, f64), Window)>));
         let drag = self.drag.clone();
+        let drag_velocity = self.drag_velocity.clone();
+        let kinetic = self.kinetic.clone();
         let click_pos = self.click_pos.clone();
+        let pressed_at = press_root.clone();
+        let source = self.source.clone();
+        let tone_map = Arc::clone(&self.tone_map);
+        let level_from = leveling.clone();
         self.window
-            .connect_button_press_event(move |window, press_event| {
+            .connect_button_press_event(move |_window, press_event| {
+                *pressed_at.borrow_mut() = press_event.get_root();
+                // right-drag on a grayscale image sets its window, only the left button pans
+                if press_event.get_button() == 3 {
+                    let grayscale = source
+                        .borrow()
+                        .as_ref()
+                        .and_then(|source| source.hdr.as_ref().map(|hdr| hdr.grayscale));
+                    if grayscale == Some(true) {
+                        let window = tone_map.lock().unwrap().window;
+                        let window = window.unwrap_or(Window::FULL);
+                        *level_from.borrow_mut() = Some((press_event.get_root(), window));
+                    }
+                }
+                if press_event.get_button() != 1 {
+                    return Inhibit::default();
+                }
+                // grab the image while it is still moving
+                stop_animation(&kinetic);
                 *drag.borrow_mut() = true;
+                drag_velocity.borrow_mut().reset(press_event.get_time());
                 let (x, y) = press_event.get_position();
                 *click_pos.borrow_mut() = [x as i32, y as i32];
-                println!("get_position {:?}", press_event.get_position());
-                // println!("get_coords {:?}", press_event.get_coords());
-                // println!("get_click_count {:?}", press_event.get_click_count());
+                debug!("get_position {:?}", press_event.get_position());
                 Inhibit::default()
             });
+        let canvas = Rc::clone(&self.canvas);
         let drag = self.drag.clone();
+        let drag_velocity = self.drag_velocity.clone();
+        let kinetic = self.kinetic.clone();
+        let kinetic_panning = self.config.kinetic_panning;
+        let refine = Rc::clone(&request_refine);
+        let source = self.source.clone();
+        let curr_scale = self.curr_scale.clone();
         let layout_xy = self.layout_xy.clone();
-        let image = Rc::clone(&self.image);
-        let layout = Rc::clone(&self.layout);
+        let wh = self.window_width_height.clone();
+        let inspector = self.inspector.clone();
+        let copy_format = self.config.inspector_copy_format;
+        let tone_map = Arc::clone(&self.tone_map);
+        let level_from = leveling.clone();
         self.window
-            .connect_button_release_event(move |window, release_event| {
+            .connect_button_release_event(move |_window, release_event| {
+                if release_event.get_button() == 3 && level_from.borrow_mut().take().is_some() {
+                    debug!("tone mapping: {}", tone_map.lock().unwrap());
+                }
+                if release_event.get_button() != 1 {
+                    return Inhibit::default();
+                }
                 *drag.borrow_mut() = false;
-                let x = layout.get_child_x(image.clone().as_ref());
-                let y = layout.get_child_y(image.clone().as_ref());
-                *layout_xy.borrow_mut() = [x, y];
-                // println!("release_event {:?}", release_event);
+
+                // a click while inspecting picks the colour of the original pixel
+                let (x, y) = release_event.get_root();
+                let (x_press, y_press) = *press_root.borrow();
+                let click = (x - x_press).abs() <= CLICK_SLOP && (y - y_press).abs() <= CLICK_SLOP;
+                if *inspector.borrow() && click && release_event.get_button() == 1 {
+                    if let Some(source) = source.borrow().as_ref() {
+                        let size = [source.width(), source.height()];
+                        let sample = canvas_xy(&canvas, (x, y))
+                            .and_then(|pos| {
+                                inspector::image_xy(
+                                    pos,
+                                    *curr_scale.borrow(),
+                                    *layout_xy.borrow(),
+                                    size,
+                                    *wh.borrow(),
+                                )
+                            })
+                            .and_then(|[x, y]| Sample::at(&source.pixbuf, x, y))
+                            .map(|sample| sample.with_original(source.hdr.as_deref()));
+                        if let Some(sample) = sample {
+                            let color = sample.format(copy_format);
+                            debug!("copied {} of pixel {}, {}", color, sample.x, sample.y);
+                            canvas
+                                .get_clipboard(&gdk::SELECTION_CLIPBOARD)
+                                .set_text(&color);
+                        }
+                    }
+                }
+
+                let velocity = drag_velocity.borrow().fling(release_event.get_time());
+                let velocity = match velocity {
+                    Some(velocity) if kinetic_panning => velocity,
+                    _ => {
+                        refine();
+                        return Inhibit::default();
+                    }
+                };
+
+                // keep the image moving after a fast drag until friction stops it
+                let fling = RefCell::new(Kinetic::new(velocity));
+                let move_by = Rc::clone(&move_view);
+                let refine = Rc::clone(&refine);
+                let kinetic_done = kinetic.clone();
+                let id = canvas.add_tick_callback(move |_canvas, frame_clock| {
+                    let step = fling.borrow_mut().step(frame_clock.get_frame_time());
+                    let moving = match step {
+                        Some([0, 0]) => true,
+                        // stop at the image borders as well
+                        Some([dx, dy]) => move_by(-dx, -dy),
+                        None => false,
+                    };
+                    if moving {
+                        return Continue(true);
+                    }
+                    kinetic_done.borrow_mut().take();
+                    refine();
+                    Continue(false)
+                });
+                *kinetic.borrow_mut() = Some(id);
                 Inhibit::default()
             });
 
-        let image = Rc::clone(&self.image);
-        let layout = Rc::clone(&self.layout);
+        // show the cursor while the mouse moves, hide it again after a while in fullscreen
+        let window = self.window.clone();
+        let fullscreen = self.fullscreen.clone();
+        let cursor_timeout = self.cursor_timeout.clone();
+        let cursor_hide_ms = self.config.cursor_hide_ms;
+        let wake_cursor = Rc::new(move || {
+            if let Some(id) = cursor_timeout.borrow_mut().take() {
+                glib::source_remove(id);
+            }
+            set_cursor_visible(&window, true);
+            if !*fullscreen.borrow() || cursor_hide_ms == 0 {
+                return;
+            }
+            let window = window.clone();
+            let cursor_timeout_done = cursor_timeout.clone();
+            let id = glib::timeout_add_local(cursor_hide_ms, move || {
+                cursor_timeout_done.borrow_mut().take();
+                set_cursor_visible(&window, false);
+                Continue(false)
+            });
+            *cursor_timeout.borrow_mut() = Some(id);
+        });
+
+        // the window manager may change the fullscreen state as well
+        let canvas = Rc::clone(&self.canvas);
+        let fullscreen = self.fullscreen.clone();
+        let refined = self.refined.clone();
+        let show_cursor = Rc::clone(&wake_cursor);
+        self.window
+            .connect_window_state_event(move |_window, state_event| {
+                let is_fullscreen = state_event
+                    .get_new_window_state()
+                    .contains(gdk::WindowState::FULLSCREEN);
+                if *fullscreen.borrow() != is_fullscreen {
+                    *fullscreen.borrow_mut() = is_fullscreen;
+                    *refined.borrow_mut() = None;
+                    show_cursor();
+                    canvas.queue_draw();
+                }
+                Inhibit(false)
+            });
+
+        let canvas = Rc::clone(&self.canvas);
+        let source = self.source.clone();
+        let curr_scale = self.curr_scale.clone();
+        let wh = self.window_width_height.clone();
         let drag = self.drag.clone();
+        let drag_velocity = self.drag_velocity.clone();
         let click_pos = self.click_pos.clone();
         let layout_xy = self.layout_xy.clone();
+        let inspector = self.inspector.clone();
+        let pointer = self.pointer.clone();
+        let tone_map = Arc::clone(&self.tone_map);
+        let tone_mapper = Rc::clone(&self.tone_mapper);
         self.window
-            .connect_motion_notify_event(move |window, motion_event| {
-                let (x_event, y_event) = motion_event.get_position();
-                let (x_root, y_root) = motion_event.get_root();
-                // println!("event {:?}", (x_event, y_event));
-                // println!("root {:?}", (x_root, y_root));
-
-                let w_width = window.get_allocated_width();
-                let w_height = window.get_allocated_height();
-
-                let x = layout_xy.borrow()[0];
-                let y = layout_xy.borrow()[1];
-                let pb = image.get_pixbuf().unwrap();
-                let w = pb.get_width();
-                let h = pb.get_height();
-                let x_shift = x + (x_event as i32 - click_pos.borrow()[0]);
-                let y_shift = y + (y_event as i32 - click_pos.borrow()[1]);
-                // println!("layout {:?}", (x, y));
-
-                // drag only if image is bigger than window
-                if *drag.borrow() && (w_width < w || w_height < h) {
-                    println!("layout {:?}", (x, y));
-                    println!("event {:?}", (x_event, y_event));
-                    println!("shift {:?}", (x_shift, y_shift));
-
-                    // keep image inside window border
-                    let x_shift = if (-(w - w_width) <= x_shift) && (x_shift <= 0) {
-                        x_shift
-                    } else if x_shift > 0 {
-                        0
-                    } else {
-                        -(w - w_width)
-                    };
-                    let y_shift = if (-(h - w_height) <= y_shift) && (y_shift <= 0) {
-                        y_shift
-                    } else if y_shift > 0 {
-                        0
-                    } else {
-                        -(h - w_height)
+            .connect_motion_notify_event(move |_window, motion_event| {
+                wake_cursor();
+                *pointer.borrow_mut() = canvas_xy(&canvas, motion_event.get_root());
+                if *inspector.borrow() {
+                    canvas.queue_draw();
+                }
+                if let Some(((x_press, y_press), start)) = *leveling.borrow() {
+                    let (x, y) = motion_event.get_root();
+                    let map = {
+                        let mut map = tone_map.lock().unwrap();
+                        map.window = Some(start.dragged(x - x_press, y - y_press));
+                        *map
                     };
-
-                    &layout.set_child_x(&image.clone().as_ref().to_owned(), x_shift);
-                    &layout.set_child_y(&image.clone().as_ref().to_owned(), y_shift);
+                    let hdr = source
+                        .borrow()
+                        .as_ref()
+                        .and_then(|source| source.hdr.clone());
+                    if let Some(hdr) = hdr {
+                        tone_mapper.request(hdr, map);
+                    }
+                    return Inhibit::default();
                 }
+                if !*drag.borrow() {
+                    return Inhibit::default();
+                }
+                let size = match source.borrow().as_ref() {
+                    Some(source) => source.scaled_size(*curr_scale.borrow()),
+                    None => return Inhibit::default(),
+                };
+                let (x_event, y_event) = motion_event.get_position();
+                let [x, y] = *layout_xy.borrow();
+                let [x_click, y_click] = *click_pos.borrow();
+                let x_shift = x + (x_event as i32 - x_click);
+                let y_shift = y + (y_event as i32 - y_click);
+                drag_velocity.borrow_mut().update(
+                    [x_event - x_click as f64, y_event - y_click as f64],
+                    motion_event.get_time(),
+                );
 
+                // keep image inside window border
+                *layout_xy.borrow_mut() = clamp_xy([x_shift, y_shift], size, *wh.borrow());
+                *click_pos.borrow_mut() = [x_event as i32, y_event as i32];
+                canvas.queue_draw();
                 Inhibit::default()
             });
 
-        // set position of the image on the center of the window while window resize
-        let image = Rc::clone(&self.image);
-        let layout = Rc::clone(&self.layout);
-        let wh = self.window_width_height.clone();
-        // self.window.connect_size_allocate(move |window, rect| {
-        self.window.connect_check_resize(move |window| {
-            let w_width = window.get_allocated_width();
-            let w_height = window.get_allocated_height();
-
-            // println!("check {:?}", rect);
-            // println!("wh w/h {:?}", *wh.borrow());
-            // println!("allocated w/h {:?}", [w_width, w_height]);
+        // nothing is under the pointer once it left the window
+        let canvas = Rc::clone(&self.canvas);
+        let inspector = self.inspector.clone();
+        let pointer = self.pointer.clone();
+        self.window
+            .connect_leave_notify_event(move |_window, _crossing_event| {
+                *pointer.borrow_mut() = None;
+                if *inspector.borrow() {
+                    canvas.queue_draw();
+                }
+                Inhibit::default()
+            });
 
-            if *wh.borrow() == [w_width, w_height] {
-                println!("here");
+        // stretch the canvas over the layout and keep the image in the center while window resize,
+        // the zoom is kept when entering or leaving fullscreen
+        let canvas = Rc::clone(&self.canvas);
+        let source = self.source.clone();
+        let curr_scale = self.curr_scale.clone();
+        let wh = self.window_width_height.clone();
+        let layout_xy = self.layout_xy.clone();
+        let refine = Rc::clone(&request_refine);
+        self.layout.connect_size_allocate(move |_layout, rect| {
+            if *wh.borrow() == [rect.width, rect.height] {
                 return;
             }
+            debug!("allocated w/h {:?}", [rect.width, rect.height]);
+            *wh.borrow_mut() = [rect.width, rect.height];
+            canvas.set_size_request(rect.width, rect.height);
 
-            println!("wh w/h {:?}", *wh.borrow());
-            println!("allocated w/h {:?}", [w_width, w_height]);
+            if let Some(source) = source.borrow().as_ref() {
+                let size = source.scaled_size(*curr_scale.borrow());
+                *layout_xy.borrow_mut() = center_xy(size, [rect.width, rect.height]);
+            }
+            refine();
+            canvas.queue_draw();
+        });
+    }
+}
 
-            // let [w, h] = wh.borrow().clone();
-            // window.resize(w, h);
+/// Remove a running frame clock animation.
+fn stop_animation(animation: &RefCell<Option<gtk::TickCallbackId>>) {
+    if let Some(id) = animation.borrow_mut().take() {
+        id.remove();
+    }
+}
 
-            let pixbuff = match (&*image).get_pixbuf() {
-                Some(pb) => pb,
-                None => return,
-            };
-            let width = pixbuff.get_width();
-            let height = pixbuff.get_height();
+/// Render the current view of `source` for the transition to the next image.
+fn snapshot_view(
+    source: &mut SourceImage,
+    scale: f64,
+    origin: [i32; 2],
+    view: [i32; 2],
+    background: Option<&gdk::RGBA>,
+    backdrop: &Backdrop,
+) -> Option<cairo::ImageSurface> {
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, view[0], view[1]).ok()?;
+    let cr = cairo::Context::new(&surface);
+    if let Some(bg) = background {
+        cr.set_source_rgba(bg.red, bg.green, bg.blue, bg.alpha);
+        cr.paint();
+    }
+    backdrop.draw(&cr, origin, source.scaled_size(scale), view);
+    source
+        .tiles
+        .draw(&cr, scale, origin, view, InterpType::Bilinear);
+    Some(surface)
+}
 
-            *wh.borrow_mut() = [w_width, w_height];
+/// Show the default cursor over the window or hide it.
+fn set_cursor_visible(window: &gtk::Window, visible: bool) {
+    let gdk_window = match window.get_window() {
+        Some(gdk_window) => gdk_window,
+        None => return,
+    };
+    if visible {
+        gdk_window.set_cursor(None);
+    } else {
+        let blank =
+            gdk::Cursor::new_for_display(&gdk_window.get_display(), gdk::CursorType::BlankCursor);
+        gdk_window.set_cursor(Some(&blank));
+    }
+}
 
-            let xwc = (w_width as f32 / 2.0) as i32;
-            let ywc = (w_height as f32 / 2.0) as i32;
-            let xic = (width as f32 / 2.0) as i32;
-            let yic = (height as f32 / 2.0) as i32;
+/// Position of the `root` screen coordinates on the canvas,
+/// events on the window may come from any of its child windows.
+fn canvas_xy(canvas: &gtk::DrawingArea, root: (f64, f64)) -> Option<[f64; 2]> {
+    let (_, x, y) = canvas.get_window()?.get_origin();
+    Some([root.0 - x as f64, root.1 - y as f64])
+}
 
-            layout.set_child_x(image.clone().as_ref(), xwc - xic);
-            layout.set_child_y(image.clone().as_ref(), ywc - yic);
-            window.resize(w_width, w_height);
-        });
+/// Show a crosshair over the canvas while inspecting pixels.
+fn set_crosshair(canvas: &gtk::DrawingArea, crosshair: bool) {
+    let gdk_window = match canvas.get_window() {
+        Some(gdk_window) => gdk_window,
+        None => return,
+    };
+    if crosshair {
+        let cursor =
+            gdk::Cursor::new_for_display(&gdk_window.get_display(), gdk::CursorType::Crosshair);
+        gdk_window.set_cursor(Some(&cursor));
+    } else {
+        gdk_window.set_cursor(None);
+    }
+}
+
+/// Ask for a zoom percentage, `None` when cancelled or not a number.
+fn prompt_zoom(window: &gtk::Window, scale: f64) -> Option<f64> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Zoom to percentage"),
+        Some(window),
+        gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Zoom", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    let entry = gtk::Entry::new();
+    entry.set_text(&format!("{:.0}%", scale * 100.0));
+    entry.set_activates_default(true);
+    dialog.get_content_area().add(&entry);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let text = entry.get_text();
+    unsafe {
+        dialog.destroy();
     }
+    match response {
+        gtk::ResponseType::Accept => parse_percentage(&text),
+        _ => None,
+    }
+}
+
+/// Part of an image of `size` pixels visible in `view` at `scale` and `origin`,
+/// as `[x, y, width, height]` in image pixels.
+fn visible_rect(size: [i32; 2], scale: f64, origin: [i32; 2], view: [i32; 2]) -> Option<[i32; 4]> {
+    let x0 = ((-origin[0]).max(0) as f64 / scale).floor() as i32;
+    let y0 = ((-origin[1]).max(0) as f64 / scale).floor() as i32;
+    let x1 = (((view[0] - origin[0]) as f64 / scale).ceil() as i32).min(size[0]);
+    let y1 = (((view[1] - origin[1]) as f64 / scale).ceil() as i32).min(size[1]);
+    if x0 >= x1 || y0 >= y1 {
+        return None;
+    }
+    Some([x0, y0, x1 - x0, y1 - y0])
+}
+
+/// Position of an image of `size` in the center of a view of `view` size.
+fn center_xy(size: [i32; 2], view: [i32; 2]) -> [i32; 2] {
+    [(view[0] - size[0]) / 2, (view[1] - size[1]) / 2]
+}
+
+/// Keep the image inside the window border: along an axis where the image
+/// is bigger than the view it can't be dragged past its edges,
+/// along an axis where it is smaller it stays in the center.
+fn clamp_xy(xy: [i32; 2], size: [i32; 2], view: [i32; 2]) -> [i32; 2] {
+    let clamp = |pos: i32, size: i32, view: i32| {
+        if size <= view {
+            (view - size) / 2
+        } else {
+            pos.max(view - size).min(0)
+        }
+    };
+    [
+        clamp(xy[0], size[0], view[0]),
+        clamp(xy[1], size[1], view[1]),
+    ]
 }
@@ -0,0 +1,160 @@
+use crate::config::Config;
+use crate::image_handler::thumbnails::{self, Thumbnails};
+use crate::image_handler::ImageSet;
+use gtk::prelude::{Inhibit, WidgetExtManual};
+use gtk::WidgetExt;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// space around the thumbnails
+const SPACING: i32 = 4;
+
+/// Strip of thumbnails under the image, centred on the current image.
+///
+/// Scrolling over the strip browses it without changing the image,
+/// the next navigation centres it again.
+pub struct Filmstrip {
+    area: Rc<gtk::DrawingArea>,
+    image_set: Rc<RefCell<ImageSet>>,
+    // shared with the gallery
+    thumbnails: Rc<Thumbnails>,
+    // horizontal scroll in pixels away from the current image
+    scroll: Rc<RefCell<f64>>,
+}
+
+impl Filmstrip {
+    pub fn new(
+        area: gtk::DrawingArea,
+        image_set: Rc<RefCell<ImageSet>>,
+        thumbnails: Rc<Thumbnails>,
+        config: &Config,
+    ) -> Self {
+        area.set_size_request(-1, config.filmstrip_height);
+        area.set_visible(config.filmstrip);
+        let area = Rc::new(area);
+        thumbnails.redraw(Rc::clone(&area));
+        Self {
+            area,
+            image_set,
+            thumbnails,
+            scroll: Rc::new(RefCell::new(0.0)),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.area.get_visible()
+    }
+
+    pub fn set_visible(&self, visible: bool) {
+        self.area.set_visible(visible);
+    }
+
+    /// Height taken from the image view, 0 when hidden.
+    pub fn height(&self) -> i32 {
+        if self.is_visible() {
+            self.area.get_size_request().1
+        } else {
+            0
+        }
+    }
+
+    /// Centre the strip on the current image again.
+    pub fn reset(&self) {
+        *self.scroll.borrow_mut() = 0.0;
+        self.thumbnails.cancel();
+    }
+
+    pub fn init_events(&mut self, open: Rc<dyn Fn(usize)>) {
+        let image_set = self.image_set.clone();
+        let thumbnails = Rc::clone(&self.thumbnails);
+        let scroll = self.scroll.clone();
+        self.area.connect_draw(move |area, cr| {
+            let image_set = image_set.borrow();
+            let width = area.get_allocated_width() as f64;
+            let height = area.get_allocated_height();
+            cr.set_source_rgba(0.0, 0.0, 0.0, 0.8);
+            cr.paint();
+            if image_set.is_empty() {
+                return Inhibit(false);
+            }
+
+            let size = (height - 2 * SPACING) as f64;
+            let cell = (height - SPACING) as f64;
+            let current = image_set.current_index() as isize;
+            let x0 = center_x(width, cell, *scroll.borrow());
+            let first = current + ((-x0) / cell).floor() as isize;
+            let last = current + ((width - x0) / cell).ceil() as isize;
+            for index in first.max(0)..=last.min(image_set.len() as isize - 1) {
+                let path = match image_set.get(index as usize) {
+                    Some(path) => path,
+                    None => break,
+                };
+                let x = x0 + (index - current) as f64 * cell;
+                let y = SPACING as f64;
+                if index == current {
+                    let pad = SPACING as f64 / 2.0;
+                    cr.set_source_rgba(0.2, 0.5, 1.0, 0.8);
+                    cr.rectangle(x - pad, y - pad, size + 2.0 * pad, size + 2.0 * pad);
+                    cr.fill();
+                }
+
+                match thumbnails.get(&path) {
+                    Some(thumb) => thumbnails::paint(cr, &thumb, x, y, size),
+                    None => {
+                        cr.set_source_rgba(0.5, 0.5, 0.5, 0.3);
+                        cr.rectangle(x, y, size, size);
+                        cr.fill();
+                    }
+                }
+            }
+            Inhibit(false)
+        });
+
+        // browse the strip with the wheel, without zooming the image
+        self.area
+            .add_events(gdk::EventMask::SCROLL_MASK | gdk::EventMask::BUTTON_PRESS_MASK);
+        let thumbnails = Rc::clone(&self.thumbnails);
+        let scroll = self.scroll.clone();
+        self.area.connect_scroll_event(move |area, scroll_event| {
+            let cell = (area.get_allocated_height() - SPACING) as f64;
+            let delta = match scroll_event.get_direction() {
+                gdk::ScrollDirection::Up | gdk::ScrollDirection::Left => -cell,
+                gdk::ScrollDirection::Down | gdk::ScrollDirection::Right => cell,
+                gdk::ScrollDirection::Smooth => {
+                    let (dx, dy) = scroll_event.get_delta();
+                    (dx + dy) * cell
+                }
+                _ => 0.0,
+            };
+            *scroll.borrow_mut() += delta;
+            // the thumbnails scrolled out of view are not worth generating anymore
+            thumbnails.cancel();
+            Inhibit(true)
+        });
+
+        // open the clicked image
+        let image_set = self.image_set.clone();
+        let scroll = self.scroll.clone();
+        self.area
+            .connect_button_press_event(move |area, press_event| {
+                let (x, _) = press_event.get_position();
+                let width = area.get_allocated_width() as f64;
+                let cell = (area.get_allocated_height() - SPACING) as f64;
+                let x0 = center_x(width, cell, *scroll.borrow());
+                let (current, len) = {
+                    let image_set = image_set.borrow();
+                    (image_set.current_index() as isize, image_set.len() as isize)
+                };
+                let index = current + ((x - x0) / cell).floor() as isize;
+                if index >= 0 && index < len && index != current {
+                    open(index as usize);
+                }
+                Inhibit(true)
+            });
+    }
+}
+
+/// Left edge of the current image's cell, centred in the strip.
+fn center_x(width: f64, cell: f64, scroll: f64) -> f64 {
+    ((width - cell) / 2.0).round() + SPACING as f64 / 2.0 - scroll
+}
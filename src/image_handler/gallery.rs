@@ -0,0 +1,351 @@
+use crate::config::Config;
+use crate::image_handler::thumbnail_cache::ThumbnailSize;
+use crate::image_handler::thumbnails::{self, Thumbnails};
+use crate::image_handler::ImageSet;
+use gtk::prelude::{AdjustmentExt, Inhibit, RangeExt, StackExt, WidgetExtManual};
+use gtk::WidgetExt;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// space around the thumbnails
+const SPACING: i32 = 12;
+// height of the file name below a thumbnail
+const LABEL_HEIGHT: i32 = 16;
+
+/// Grid of thumbnails of every image in the set.
+///
+/// Only the visible rows are drawn and only their thumbnails are generated,
+/// so the grid stays responsive for folders with thousands of images.
+pub struct Gallery {
+    stack: gtk::Stack,
+    area: Rc<gtk::DrawingArea>,
+    scrollbar: gtk::Scrollbar,
+    size_scale: gtk::Scale,
+    image_set: Rc<RefCell<ImageSet>>,
+    // shared with the filmstrip, their size follows the slider
+    thumbnails: Rc<Thumbnails>,
+    selected: Rc<RefCell<usize>>,
+    thumb_size: Rc<RefCell<i32>>,
+}
+
+/// Cell sizes and number of columns for the current width and thumbnail size.
+#[derive(Debug, Clone, Copy)]
+struct Grid {
+    cell_w: i32,
+    cell_h: i32,
+    columns: usize,
+    // left margin centering the columns
+    x0: i32,
+}
+
+impl Grid {
+    fn new(width: i32, thumb_size: i32) -> Self {
+        let cell_w = thumb_size + SPACING;
+        let cell_h = thumb_size + LABEL_HEIGHT + SPACING;
+        let columns = ((width - SPACING) / cell_w).max(1);
+        Self {
+            cell_w,
+            cell_h,
+            columns: columns as usize,
+            x0: ((width - SPACING - columns * cell_w) / 2).max(0) + SPACING,
+        }
+    }
+
+    fn rows(&self, len: usize) -> usize {
+        len.div_ceil(self.columns)
+    }
+
+    fn height(&self, len: usize) -> i32 {
+        self.rows(len) as i32 * self.cell_h + SPACING
+    }
+
+    /// Top left corner of the thumbnail at `index` scrolled by `offset`.
+    fn cell_xy(&self, index: usize, offset: f64) -> [f64; 2] {
+        let col = (index % self.columns) as i32;
+        let row = (index / self.columns) as i32;
+        [
+            (self.x0 + col * self.cell_w) as f64,
+            (SPACING + row * self.cell_h) as f64 - offset,
+        ]
+    }
+
+    /// Index of the cell under `xy` scrolled by `offset`.
+    fn index_at(&self, xy: [f64; 2], offset: f64) -> Option<usize> {
+        let x = xy[0] as i32 - self.x0;
+        let y = (xy[1] + offset) as i32 - SPACING;
+        if x < 0 || y < 0 {
+            return None;
+        }
+        let col = (x / self.cell_w) as usize;
+        if col >= self.columns {
+            return None;
+        }
+        Some((y / self.cell_h) as usize * self.columns + col)
+    }
+}
+
+impl Gallery {
+    pub fn new(
+        stack: gtk::Stack,
+        area: gtk::DrawingArea,
+        scrollbar: gtk::Scrollbar,
+        size_scale: gtk::Scale,
+        image_set: Rc<RefCell<ImageSet>>,
+        thumbnails: Rc<Thumbnails>,
+        config: &Config,
+    ) -> Self {
+        size_scale.set_value(config.thumbnail_size as f64);
+        let area = Rc::new(area);
+        thumbnails.redraw(Rc::clone(&area));
+        Self {
+            stack,
+            area,
+            scrollbar,
+            size_scale,
+            image_set,
+            thumbnails,
+            selected: Rc::new(RefCell::new(0)),
+            thumb_size: Rc::new(RefCell::new(config.thumbnail_size)),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.stack.get_visible_child_name().as_deref() == Some("gallery")
+    }
+
+    /// Switch to the grid with the current image selected.
+    pub fn show(&self) {
+        *self.selected.borrow_mut() = self.image_set.borrow().current_index();
+        self.stack.set_visible_child_name("gallery");
+        self.update_scrollbar();
+        self.scroll_to_selected();
+    }
+
+    /// Switch back to the single image view.
+    pub fn hide(&self) {
+        self.stack.set_visible_child_name("viewer");
+        self.thumbnails.cancel();
+    }
+
+    pub fn selected(&self) -> usize {
+        *self.selected.borrow()
+    }
+
+    /// Select the image at `index`, clamped to the last image.
+    pub fn select(&self, index: usize) {
+        let len = self.image_set.borrow().len();
+        if len == 0 {
+            return;
+        }
+        *self.selected.borrow_mut() = index.min(len - 1);
+        self.scroll_to_selected();
+        self.area.queue_draw();
+    }
+
+    /// Move the selection by `delta` cells.
+    pub fn move_selection(&self, delta: isize) {
+        let selected = *self.selected.borrow() as isize + delta;
+        self.select(selected.max(0) as usize);
+    }
+
+    pub fn columns(&self) -> isize {
+        self.grid().columns as isize
+    }
+
+    /// Number of whole rows fitting on screen.
+    pub fn page_rows(&self) -> isize {
+        let height = self.area.get_allocated_height();
+        (height / self.grid().cell_h).max(1) as isize
+    }
+
+    pub fn init_events(&mut self, open: Rc<dyn Fn(usize)>) {
+        let adjustment = self.scrollbar.get_adjustment();
+
+        // draw the visible rows, thumbnails missing in the cache are requested on the way
+        let image_set = self.image_set.clone();
+        let thumbnails = Rc::clone(&self.thumbnails);
+        let selected = self.selected.clone();
+        let thumb_size = self.thumb_size.clone();
+        let scroll = adjustment.clone();
+        self.area.connect_draw(move |area, cr| {
+            let image_set = image_set.borrow();
+            if image_set.is_empty() {
+                return Inhibit(false);
+            }
+            let size = *thumb_size.borrow();
+            let width = area.get_allocated_width();
+            let height = area.get_allocated_height();
+            let grid = Grid::new(width, size);
+            let offset = scroll.get_value();
+            let first_row = (offset as i32 / grid.cell_h) as usize;
+            let last_row = ((offset as i32 + height) / grid.cell_h) as usize;
+            let first = first_row * grid.columns;
+            let last = ((last_row + 1) * grid.columns).min(image_set.len());
+
+            cr.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+            cr.set_font_size(11.0);
+            for index in first..last {
+                let path = match image_set.get(index) {
+                    Some(path) => path,
+                    None => break,
+                };
+                let [x, y] = grid.cell_xy(index, offset);
+                let size = size as f64;
+                if index == *selected.borrow() {
+                    let pad = SPACING as f64 / 2.0;
+                    cr.set_source_rgba(0.2, 0.5, 1.0, 0.5);
+                    cr.rectangle(
+                        x - pad,
+                        y - pad,
+                        size + 2.0 * pad,
+                        size + LABEL_HEIGHT as f64 + pad,
+                    );
+                    cr.fill();
+                }
+
+                match thumbnails.get(&path) {
+                    Some(thumb) => thumbnails::paint(cr, &thumb, x, y, size),
+                    None => {
+                        cr.set_source_rgba(0.5, 0.5, 0.5, 0.2);
+                        cr.rectangle(x, y, size, size);
+                        cr.fill();
+                        if thumbnails.has_failed(&path) {
+                            cr.set_source_rgba(0.8, 0.2, 0.2, 0.6);
+                            cr.move_to(x, y);
+                            cr.line_to(x + size, y + size);
+                            cr.move_to(x + size, y);
+                            cr.line_to(x, y + size);
+                            cr.stroke();
+                        }
+                    }
+                }
+
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                cr.save();
+                cr.rectangle(x, y + size, size, LABEL_HEIGHT as f64);
+                cr.clip();
+                cr.set_source_rgb(0.5, 0.5, 0.5);
+                cr.move_to(x, y + size + LABEL_HEIGHT as f64 - 3.0);
+                cr.show_text(&name);
+                cr.restore();
+            }
+            Inhibit(false)
+        });
+
+        // thumbnails scrolled out of view are not worth generating anymore
+        let thumbnails = Rc::clone(&self.thumbnails);
+        adjustment.connect_value_changed(move |_adjustment| {
+            thumbnails.cancel();
+        });
+
+        let image_set = self.image_set.clone();
+        let thumb_size = self.thumb_size.clone();
+        let scroll = adjustment.clone();
+        self.area.connect_size_allocate(move |_area, rect| {
+            let grid = Grid::new(rect.width, *thumb_size.borrow());
+            let upper = grid.height(image_set.borrow().len()) as f64;
+            configure_scroll(&scroll, upper, rect.height as f64, grid.cell_h as f64);
+        });
+
+        // scroll by a third of a row per wheel step
+        self.area.add_events(gdk::EventMask::SCROLL_MASK);
+        let thumb_size = self.thumb_size.clone();
+        let scroll = adjustment.clone();
+        self.area.connect_scroll_event(move |_area, scroll_event| {
+            let step = (*thumb_size.borrow() + LABEL_HEIGHT + SPACING) as f64 / 3.0;
+            let delta = match scroll_event.get_direction() {
+                gdk::ScrollDirection::Up => -step,
+                gdk::ScrollDirection::Down => step,
+                gdk::ScrollDirection::Smooth => scroll_event.get_delta().1 * step,
+                _ => 0.0,
+            };
+            scroll.set_value(scroll.get_value() + delta);
+            // keep the window from zooming the hidden image
+            Inhibit(true)
+        });
+
+        // a click selects a thumbnail, a double click opens it
+        self.area.add_events(gdk::EventMask::BUTTON_PRESS_MASK);
+        let image_set = self.image_set.clone();
+        let selected = self.selected.clone();
+        let thumb_size = self.thumb_size.clone();
+        let scroll = adjustment.clone();
+        self.area
+            .connect_button_press_event(move |area, press_event| {
+                let grid = Grid::new(area.get_allocated_width(), *thumb_size.borrow());
+                let (x, y) = press_event.get_position();
+                let index = match grid.index_at([x, y], scroll.get_value()) {
+                    Some(index) if index < image_set.borrow().len() => index,
+                    _ => return Inhibit(true),
+                };
+                *selected.borrow_mut() = index;
+                area.queue_draw();
+                if press_event.get_event_type() == gdk::EventType::DoubleButtonPress {
+                    open(index);
+                }
+                Inhibit(true)
+            });
+
+        // resize the thumbnails with the slider, keeping the selection in view,
+        // past the size of the cached thumbnails they are loaded again at the next size
+        let area = Rc::clone(&self.area);
+        let image_set = self.image_set.clone();
+        let selected = self.selected.clone();
+        let thumb_size = self.thumb_size.clone();
+        let thumbnails = Rc::clone(&self.thumbnails);
+        let scroll = adjustment;
+        self.size_scale.connect_value_changed(move |size_scale| {
+            *thumb_size.borrow_mut() = size_scale.get_value() as i32;
+            thumbnails.set_size(ThumbnailSize::for_pixels(*thumb_size.borrow()));
+            let grid = Grid::new(area.get_allocated_width(), *thumb_size.borrow());
+            let upper = grid.height(image_set.borrow().len()) as f64;
+            let page = area.get_allocated_height() as f64;
+            configure_scroll(&scroll, upper, page, grid.cell_h as f64);
+            let [_, y] = grid.cell_xy(*selected.borrow(), 0.0);
+            scroll.set_value(y - (page - grid.cell_h as f64) / 2.0);
+            area.queue_draw();
+        });
+    }
+
+    fn grid(&self) -> Grid {
+        Grid::new(self.area.get_allocated_width(), *self.thumb_size.borrow())
+    }
+
+    fn update_scrollbar(&self) {
+        let grid = self.grid();
+        let upper = grid.height(self.image_set.borrow().len()) as f64;
+        let page = self.area.get_allocated_height() as f64;
+        configure_scroll(
+            &self.scrollbar.get_adjustment(),
+            upper,
+            page,
+            grid.cell_h as f64,
+        );
+    }
+
+    /// Scroll just enough for the selected thumbnail to be fully visible.
+    fn scroll_to_selected(&self) {
+        let adjustment = self.scrollbar.get_adjustment();
+        let grid = self.grid();
+        let [_, top] = grid.cell_xy(*self.selected.borrow(), 0.0);
+        let top = top - SPACING as f64 / 2.0;
+        let bottom = top + grid.cell_h as f64;
+        let page = self.area.get_allocated_height() as f64;
+        let offset = adjustment.get_value();
+        if top < offset {
+            adjustment.set_value(top);
+        } else if bottom > offset + page {
+            adjustment.set_value(bottom - page);
+        }
+        self.area.queue_draw();
+    }
+}
+
+/// Set the range of the gallery scrollbar, the value is clamped by GTK.
+fn configure_scroll(adjustment: &gtk::Adjustment, upper: f64, page: f64, row: f64) {
+    let value = adjustment.get_value();
+    adjustment.configure(value, 0.0, upper, row / 3.0, page, page);
+}
@@ -0,0 +1,507 @@
+use crate::image_handler::color::ColorManager;
+use crate::image_handler::loader::PixelData;
+use crate::image_handler::probe::Probe;
+use crate::image_handler::window_level::{Lut, Window};
+use gdk_pixbuf::Pixbuf;
+use image::codecs::png::PngDecoder;
+use image::codecs::tiff::TiffDecoder;
+use image::io::Limits;
+use image::{DynamicImage, ImageDecoder, ImageFormat};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+// gamma 16 bit PNG and TIFF files are taken to be encoded with
+const FILE_GAMMA: f32 = 2.2;
+// samples enough for stable percentiles of any image size
+const PERCENTILE_SAMPLES: usize = 1 << 20;
+
+/// Operator compressing the range of the exposed linear values into the display range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapping {
+    /// cut everything above white
+    Clamp,
+    /// `x / (1 + x)`
+    Reinhard,
+    /// fit of the ACES filmic curve by Krzysztof Narkowicz
+    Aces,
+}
+
+impl ToneMapping {
+    /// The operator after this one, in the order the key cycles through them.
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapping::Clamp => ToneMapping::Reinhard,
+            ToneMapping::Reinhard => ToneMapping::Aces,
+            ToneMapping::Aces => ToneMapping::Clamp,
+        }
+    }
+
+    fn apply(self, x: f32) -> f32 {
+        let x = x.max(0.0);
+        let mapped = match self {
+            ToneMapping::Clamp => x,
+            ToneMapping::Reinhard => x / (1.0 + x),
+            ToneMapping::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+        };
+        mapped.min(1.0)
+    }
+}
+
+impl FromStr for ToneMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMapping::Clamp),
+            "reinhard" => Ok(ToneMapping::Reinhard),
+            "aces" => Ok(ToneMapping::Aces),
+            _ => Err(format!("unknown tone mapping `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for ToneMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ToneMapping::Clamp => "clamp",
+            ToneMapping::Reinhard => "reinhard",
+            ToneMapping::Aces => "aces",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How high precision pixels are brought to the 8 bits of the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMap {
+    /// stops the linear values are scaled by before tone mapping
+    pub exposure: f32,
+    /// gamma of the display the mapped values are encoded for
+    pub gamma: f32,
+    pub operator: ToneMapping,
+    /// values of grayscale images shown instead of tone mapping them
+    pub window: Option<Window>,
+    /// colours of grayscale images
+    pub lut: Lut,
+    /// `window` is kept for the next images instead of being chosen for each one
+    pub keep_window: bool,
+}
+
+impl ToneMap {
+    /// Settings for another image: the window of the one shown until now is dropped,
+    /// unless it is kept.
+    pub fn next_image(&mut self) {
+        if !self.keep_window {
+            self.window = None;
+        }
+    }
+}
+
+impl fmt::Display for ToneMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "exposure {:+.1} EV, gamma {:.1}, {}",
+            self.exposure, self.gamma, self.operator
+        )?;
+        if let Some(window) = self.window {
+            write!(f, ", window {}", window)?;
+        }
+        write!(f, ", {} lookup table", self.lut)
+    }
+}
+
+/// Image decoded at full precision, 16 bit samples scaled to 0..1 and float samples as they are.
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    /// single channel, with alpha when the file has it
+    pub grayscale: bool,
+    // samples per pixel: gray, gray and alpha or RGBA
+    channels: usize,
+    pixels: Vec<f32>,
+    // scene linear values of OpenEXR and Radiance files, gamma encoded otherwise
+    linear: bool,
+    // ICC profile embedded in the file
+    icc: Option<Vec<u8>>,
+}
+
+impl HdrImage {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut reader = image::io::Reader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|err| err.to_string())?;
+        let format = reader.format();
+        let linear = matches!(format, Some(ImageFormat::OpenExr) | Some(ImageFormat::Hdr));
+        // the float pixels of big renders are well over the default limit
+        reader.no_limits();
+        // the decoders of PNG and TIFF are used directly for their embedded profile
+        let (decoded, icc) = match format {
+            Some(ImageFormat::Png) => {
+                let mut decoder = PngDecoder::with_limits(reader.into_inner(), Limits::no_limits())
+                    .map_err(|err| err.to_string())?;
+                let icc = decoder.icc_profile();
+                (DynamicImage::from_decoder(decoder), icc)
+            }
+            Some(ImageFormat::Tiff) => {
+                let mut decoder =
+                    TiffDecoder::new(reader.into_inner()).map_err(|err| err.to_string())?;
+                let icc = decoder.icc_profile();
+                (DynamicImage::from_decoder(decoder), icc)
+            }
+            _ => (reader.decode(), None),
+        };
+        let decoded = decoded.map_err(|err| err.to_string())?;
+        let width = decoded.width() as usize;
+        let height = decoded.height() as usize;
+        let color = decoded.color();
+        let grayscale = !color.has_color();
+        let (channels, pixels) = match (grayscale, color.has_alpha()) {
+            (true, true) => (2, decoded.to_luma_alpha32f().into_raw()),
+            (true, false) => (1, decoded.to_luma32f().into_raw()),
+            (false, _) => (4, decoded.into_rgba32f().into_raw()),
+        };
+        Ok(Self {
+            width,
+            height,
+            grayscale,
+            channels,
+            pixels,
+            linear,
+            icc,
+        })
+    }
+
+    /// Convert the colours of the image decoded from `path` for the display.
+    /// Scene linear and grayscale values are kept as they are.
+    /// Returns whether the pixels were converted.
+    pub fn convert_colors(&mut self, path: &Path, color: &ColorManager) -> Result<bool, String> {
+        if self.linear || self.grayscale {
+            return Ok(false);
+        }
+        color.convert_float(path, self.icc.as_deref(), &mut self.pixels)
+    }
+
+    /// Gray values of an 8 bit pixbuf with only gray pixels, so that they can be windowed
+    /// and coloured like those of 16 bit images. `None` for colour images.
+    /// Slow for big images, it is meant for the tone mapping thread.
+    pub fn from_gray_pixbuf(pixbuf: &Pixbuf) -> Option<Self> {
+        let width = pixbuf.get_width() as usize;
+        let height = pixbuf.get_height() as usize;
+        let stride = pixbuf.get_n_channels() as usize;
+        let rowstride = pixbuf.get_rowstride() as usize;
+        if pixbuf.get_bits_per_sample() != 8 || stride < 3 {
+            return None;
+        }
+        let channels = if pixbuf.get_has_alpha() { 2 } else { 1 };
+        // pixbufs made from bytes, as the ones handed to the threads, are read without a copy
+        let bytes = pixbuf.read_pixel_bytes()?;
+        let mut pixels = Vec::with_capacity(width * height * channels);
+        for y in 0..height {
+            let row = bytes.get(y * rowstride..y * rowstride + width * stride)?;
+            for pixel in row.chunks_exact(stride) {
+                if pixel[0] != pixel[1] || pixel[0] != pixel[2] {
+                    return None;
+                }
+                pixels.push(pixel[0] as f32 / 255.0);
+                if channels == 2 {
+                    pixels.push(pixel[3] as f32 / 255.0);
+                }
+            }
+        }
+        Some(Self {
+            width,
+            height,
+            grayscale: true,
+            channels,
+            pixels,
+            linear: false,
+            icc: None,
+        })
+    }
+
+    /// Full precision RGBA values of the pixel at `x`, `y`, after the colour conversion.
+    pub fn pixel(&self, x: i32, y: i32) -> Option<[f32; 4]> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        let i = (y as usize * self.width + x as usize) * self.channels;
+        match *self.pixels.get(i..i + self.channels)? {
+            [value] => Some([value, value, value, 1.0]),
+            [value, alpha] => Some([value, value, value, alpha]),
+            [r, g, b, a] => Some([r, g, b, a]),
+            _ => None,
+        }
+    }
+
+    /// Window spanning the `percentiles` of the values of a grayscale image.
+    pub fn auto_window(&self, percentiles: [f32; 2]) -> Window {
+        // large images are sampled evenly
+        let step = (self.width * self.height / PERCENTILE_SAMPLES).max(1);
+        let values = self.pixels.chunks_exact(self.channels).step_by(step);
+        Window::from_percentiles(values.map(|pixel| pixel[0]).collect(), percentiles)
+    }
+
+    /// Render 8 bit RGBA pixels with `map`, `None` once `cancelled` reports true.
+    /// The window and lookup table of `map` only apply to grayscale images.
+    pub fn render<F: Fn() -> bool>(&self, map: ToneMap, cancelled: F) -> Option<PixelData> {
+        let gain = 2f32.powf(map.exposure);
+        let encode = 1.0 / map.gamma.max(0.1);
+        let tone = |value: f32| {
+            let linear = if self.linear {
+                value
+            } else {
+                value.max(0.0).powf(FILE_GAMMA)
+            };
+            map.operator.apply(linear * gain).powf(encode)
+        };
+        let mut bytes = Vec::with_capacity(self.width * self.height * 4);
+        let row_len = self.width.max(1) * self.channels;
+        for (y, row) in self.pixels.chunks_exact(row_len).enumerate() {
+            // checked every few rows, that is often enough to stop early
+            if y % 64 == 0 && cancelled() {
+                return None;
+            }
+            for pixel in row.chunks_exact(self.channels) {
+                if self.grayscale {
+                    let value = match map.window {
+                        Some(window) => window.apply(pixel[0]),
+                        None => tone(pixel[0]),
+                    };
+                    bytes.extend_from_slice(&map.lut.color(value));
+                } else {
+                    for &value in &pixel[..3] {
+                        bytes.push((tone(value) * 255.0 + 0.5) as u8);
+                    }
+                }
+                // gray pixels may have no alpha, it comes last otherwise
+                let alpha = match *pixel {
+                    [_] => 1.0,
+                    [.., alpha] => alpha,
+                    [] => 1.0,
+                };
+                bytes.push((alpha.clamp(0.0, 1.0) * 255.0 + 0.5) as u8);
+            }
+        }
+        Some(PixelData::from_rgba(
+            self.width as i32,
+            self.height as i32,
+            bytes,
+        ))
+    }
+}
+
+/// Whether `path` holds more than the 8 bits per sample a pixbuf keeps:
+/// OpenEXR, Radiance and 16 bit PNG or TIFF files.
+pub fn is_high_precision(path: &Path) -> bool {
+    let mut magic = [0u8; 25];
+    let read = match File::open(path).and_then(|mut file| file.read(&mut magic)) {
+        Ok(read) => read,
+        Err(_) => return false,
+    };
+    let magic = &magic[..read];
+    if magic.starts_with(&[0x76, 0x2f, 0x31, 0x01]) || magic.starts_with(b"#?") {
+        return true;
+    }
+    if magic.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // bit depth in the IHDR chunk
+        return magic.get(24) == Some(&16);
+    }
+    if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") {
+        return Probe::tiff_bit_depth(path).unwrap_or(8) > 8;
+    }
+    false
+}
+
+/// Result of the tone mapping thread.
+pub enum ToneMapped {
+    /// pixels rendered again after the tone mapping changed
+    Rendered {
+        generation: usize,
+        pixels: PixelData,
+    },
+    /// gray values of an 8 bit image found to be grayscale
+    Gray {
+        generation: usize,
+        image: Arc<HdrImage>,
+    },
+}
+
+enum Job {
+    Render {
+        generation: usize,
+        image: Arc<HdrImage>,
+        map: ToneMap,
+    },
+    Examine {
+        generation: usize,
+        pixels: PixelData,
+    },
+}
+
+/// Background thread rendering high precision images again with new tone mapping settings.
+/// It also looks for 8 bit images with only gray pixels, to window them as well.
+pub struct ToneMapper {
+    jobs: mpsc::Sender<Job>,
+    generation: Arc<AtomicUsize>,
+    // generation of the image examined for gray pixels
+    examined: Arc<AtomicUsize>,
+}
+
+impl ToneMapper {
+    pub fn new(results: glib::Sender<ToneMapped>) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let generation = Arc::new(AtomicUsize::new(0));
+        let examined = Arc::new(AtomicUsize::new(0));
+        let current = Arc::clone(&generation);
+        let current_examined = Arc::clone(&examined);
+        thread::spawn(move || {
+            for job in queue {
+                let result = match job {
+                    Job::Render {
+                        generation,
+                        image,
+                        map,
+                    } => {
+                        let cancelled = || generation != current.load(Ordering::SeqCst);
+                        if cancelled() {
+                            continue;
+                        }
+                        match image.render(map, cancelled) {
+                            Some(pixels) => ToneMapped::Rendered { generation, pixels },
+                            None => continue,
+                        }
+                    }
+                    Job::Examine { generation, pixels } => {
+                        if generation != current_examined.load(Ordering::SeqCst) {
+                            continue;
+                        }
+                        match HdrImage::from_gray_pixbuf(&pixels.to_pixbuf()) {
+                            Some(image) => ToneMapped::Gray {
+                                generation,
+                                image: Arc::new(image),
+                            },
+                            None => continue,
+                        }
+                    }
+                };
+                if results.send(result).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            jobs,
+            generation,
+            examined,
+        }
+    }
+
+    /// Queue `image` for rendering with `map`, cancelling the previous request.
+    pub fn request(&self, image: Arc<HdrImage>, map: ToneMap) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.jobs
+            .send(Job::Render {
+                generation,
+                image,
+                map,
+            })
+            .unwrap();
+    }
+
+    /// Queue the 8 bit `pixels` of the image just shown to be looked at for gray pixels.
+    pub fn examine(&self, pixels: PixelData) {
+        let generation = self.examined.fetch_add(1, Ordering::SeqCst) + 1;
+        self.jobs.send(Job::Examine { generation, pixels }).unwrap();
+    }
+
+    /// Cancel the rendering and the examination of the image shown until now.
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.examined.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn is_current(&self, generation: usize) -> bool {
+        generation == self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Whether the gray values of `generation` belong to the image shown now.
+    pub fn is_examined(&self, generation: usize) -> bool {
+        generation == self.examined.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(channels: usize, pixels: Vec<f32>) -> HdrImage {
+        HdrImage {
+            width: 2,
+            height: 2,
+            grayscale: true,
+            channels,
+            pixels,
+            linear: false,
+            icc: None,
+        }
+    }
+
+    fn tone_map(keep_window: bool) -> ToneMap {
+        ToneMap {
+            exposure: 0.0,
+            gamma: 2.2,
+            operator: ToneMapping::Clamp,
+            window: Some(Window::between(0.2, 0.4)),
+            lut: Lut::Gray,
+            keep_window,
+        }
+    }
+
+    #[test]
+    fn next_image_drops_the_window() {
+        let mut map = tone_map(false);
+        map.next_image();
+        assert_eq!(map.window, None);
+        assert_eq!(map.exposure, 0.0);
+        assert_eq!(map.lut, Lut::Gray);
+    }
+
+    #[test]
+    fn kept_window_stays_for_the_next_image() {
+        let mut map = tone_map(true);
+        map.next_image();
+        assert_eq!(map.window, Some(Window::between(0.2, 0.4)));
+    }
+
+    #[test]
+    fn gray_pixels_have_a_single_channel() {
+        let image = gray(1, vec![0.0, 0.25, 0.5, 1.0]);
+        assert_eq!(image.pixel(1, 0), Some([0.25, 0.25, 0.25, 1.0]));
+        assert_eq!(image.pixel(1, 1), Some([1.0, 1.0, 1.0, 1.0]));
+        assert_eq!(image.pixel(2, 0), None);
+        assert_eq!(image.pixel(0, -1), None);
+        let window = image.auto_window([0.0, 100.0]);
+        assert_eq!(window, Window::between(0.0, 1.0));
+    }
+
+    #[test]
+    fn gray_pixels_keep_their_alpha() {
+        let image = gray(2, vec![0.0, 1.0, 0.25, 0.5, 0.5, 0.0, 1.0, 1.0]);
+        assert_eq!(image.pixel(1, 0), Some([0.25, 0.25, 0.25, 0.5]));
+        assert_eq!(image.pixel(0, 1), Some([0.5, 0.5, 0.5, 0.0]));
+        // alpha is not a value
+        let window = image.auto_window([0.0, 100.0]);
+        assert_eq!(window, Window::between(0.0, 1.0));
+        let window =
+            gray(2, vec![0.25, 1.0, 0.25, 0.0, 0.5, 1.0, 0.5, 0.0]).auto_window([0.0, 100.0]);
+        assert_eq!(window, Window::between(0.25, 0.5));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::image_handler::loader::PixelData;
+use gdk_pixbuf::Pixbuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+/// Pixel counts per 8 bit level of the luma and of every channel.
+pub struct Histogram {
+    pub generation: usize,
+    pub luma: [u32; 256],
+    pub red: [u32; 256],
+    pub green: [u32; 256],
+    pub blue: [u32; 256],
+}
+
+impl Histogram {
+    /// Highest count of any level, the height the histogram is scaled to.
+    pub fn max(&self) -> u32 {
+        [&self.luma, &self.red, &self.green, &self.blue]
+            .iter()
+            .flat_map(|counts| counts.iter())
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+struct Job {
+    generation: usize,
+    src: PixelData,
+}
+
+/// Background thread counting the levels of the image or of its visible part.
+pub struct Histogrammer {
+    jobs: mpsc::Sender<Job>,
+    generation: Arc<AtomicUsize>,
+}
+
+impl Histogrammer {
+    pub fn new(results: glib::Sender<Histogram>) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let generation = Arc::new(AtomicUsize::new(0));
+        let current = Arc::clone(&generation);
+        thread::spawn(move || {
+            for job in queue {
+                let cancelled = || job.generation != current.load(Ordering::SeqCst);
+                if cancelled() {
+                    continue;
+                }
+                let histogram = match compute(&job.src.to_pixbuf(), job.generation, cancelled) {
+                    Some(histogram) => histogram,
+                    None => continue,
+                };
+                if results.send(histogram).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { jobs, generation }
+    }
+
+    /// Queue the histogram of `region` of `pixbuf`, `[x, y, width, height]` in image pixels,
+    /// or of the whole image. Cancels the previous request.
+    pub fn request(&self, pixbuf: &Pixbuf, region: Option<[i32; 4]>) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        // the source shares its pixels, only a region is copied
+        let src = match region {
+            Some([x, y, width, height]) => pixbuf
+                .new_subpixbuf(x, y, width, height)
+                .and_then(|sub| PixelData::from_pixbuf(&sub)),
+            None => PixelData::from_pixbuf(pixbuf),
+        };
+        if let Some(src) = src {
+            self.jobs.send(Job { generation, src }).unwrap();
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn is_current(&self, generation: usize) -> bool {
+        generation == self.generation.load(Ordering::SeqCst)
+    }
+}
+
+/// Count the levels of the opaque pixels, `None` once `cancelled` reports true.
+/// Luma is weighted with the Rec. 709 coefficients.
+fn compute<F: Fn() -> bool>(pixbuf: &Pixbuf, generation: usize, cancelled: F) -> Option<Histogram> {
+    let mut histogram = Histogram {
+        generation,
+        luma: [0; 256],
+        red: [0; 256],
+        green: [0; 256],
+        blue: [0; 256],
+    };
+    let bytes = pixbuf.read_pixel_bytes()?;
+    let channels = pixbuf.get_n_channels() as usize;
+    let has_alpha = pixbuf.get_has_alpha();
+    let rowstride = pixbuf.get_rowstride() as usize;
+    let width = pixbuf.get_width() as usize;
+    for y in 0..pixbuf.get_height() as usize {
+        // checked once per row, that is often enough to stop early
+        if cancelled() {
+            return None;
+        }
+        let start = y * rowstride;
+        let row = match bytes.get(start..start + width * channels) {
+            Some(row) => row,
+            None => break,
+        };
+        for pixel in row.chunks_exact(channels) {
+            if has_alpha && pixel[3] == 0 {
+                continue;
+            }
+            let (r, g, b) = (pixel[0] as usize, pixel[1] as usize, pixel[2] as usize);
+            histogram.red[r] += 1;
+            histogram.green[g] += 1;
+            histogram.blue[b] += 1;
+            histogram.luma[(54 * r + 183 * g + 19 * b) >> 8] += 1;
+        }
+    }
+    Some(histogram)
+}
@@ -5,38 +5,45 @@ pub struct ImageSet {
     images: Vec<PathBuf>,
     curr_image_index: usize,
     len: usize,
+    // +1 after `next`, -1 after `prev`
+    direction: isize,
+    // slideshow order in shuffle mode, a permutation of the image indices
+    shuffled: Vec<usize>,
+    shuffle_pos: usize,
 }
 
 impl ImageSet {
     pub fn new(mut images: Vec<PathBuf>, curr_image_path: &PathBuf) -> Self {
         images.sort();
-        let curr_image_index = Self::get_image_index(&images, curr_image_path);
         let len = images.len();
-        if curr_image_index == 0 {
-            Self {
-                images: vec![],
-                curr_image_index: 0,
-                len: 0,
-            }
-        } else {
-            Self {
+        match Self::get_image_index(&images, curr_image_path) {
+            Some(curr_image_index) => Self {
                 images,
                 curr_image_index,
                 len,
-            }
+                direction: 1,
+                shuffled: vec![],
+                shuffle_pos: 0,
+            },
+            None => Self {
+                images: vec![],
+                curr_image_index: 0,
+                len: 0,
+                direction: 1,
+                shuffled: vec![],
+                shuffle_pos: 0,
+            },
         }
     }
-    fn get_image_index(images: &Vec<PathBuf>, curr_image_path: &PathBuf) -> usize {
-        images
-            .iter()
-            .position(|r| r == curr_image_path)
-            .unwrap_or_default()
+    fn get_image_index(images: &[PathBuf], curr_image_path: &PathBuf) -> Option<usize> {
+        images.iter().position(|r| r == curr_image_path)
     }
 
     pub fn next(&mut self) -> Option<PathBuf> {
         if self.len == 0 {
             return None;
         }
+        self.direction = 1;
         self.curr_image_index = (self.curr_image_index + 1) % self.len;
         Some(PathBuf::from(
             self.images.get(self.curr_image_index).unwrap(),
@@ -47,13 +54,193 @@ impl ImageSet {
         if self.len == 0 {
             return None;
         }
-        self.curr_image_index = if self.curr_image_index > 0 {
-            ((self.curr_image_index as i32 - 1) % self.len as i32).abs() as usize
-        } else {
-            self.len - 1
-        };
+        self.direction = -1;
+        self.curr_image_index = (self.curr_image_index + self.len - 1) % self.len;
         Some(PathBuf::from(
             self.images.get(self.curr_image_index).unwrap(),
         ))
     }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.curr_image_index
+    }
+
+    pub fn get(&self, index: usize) -> Option<PathBuf> {
+        self.images.get(index).cloned()
+    }
+
+    /// Make the image at `index` the current one.
+    pub fn go_to(&mut self, index: usize) -> Option<PathBuf> {
+        let path = self.get(index)?;
+        self.direction = if index < self.curr_image_index { -1 } else { 1 };
+        self.curr_image_index = index;
+        Some(path)
+    }
+
+    /// Image `offset` steps away from the current one, wrapping around like `next`/`prev`.
+    pub fn peek(&self, offset: isize) -> Option<PathBuf> {
+        if self.len == 0 {
+            return None;
+        }
+        let index = (self.curr_image_index as isize + offset).rem_euclid(self.len as isize);
+        Some(PathBuf::from(self.images.get(index as usize).unwrap()))
+    }
+
+    /// Up to `n` neighbours on each side of the current image,
+    /// nearest first and the navigation direction before the opposite one.
+    /// In shuffle mode the next images of the slideshow come first.
+    pub fn neighbours(&self, n: usize) -> Vec<PathBuf> {
+        let n = n.min(self.len.saturating_sub(1)) as isize;
+        let curr = self.peek(0);
+        let mut neighbours: Vec<PathBuf> = self
+            .shuffled
+            .iter()
+            .skip(self.shuffle_pos + 1)
+            .take(n as usize)
+            .map(|index| self.images[*index].clone())
+            .collect();
+        let ahead = (1..=n).map(|step| step * self.direction);
+        let behind = (1..=n).map(|step| -step * self.direction);
+        for path in ahead.chain(behind).filter_map(|offset| self.peek(offset)) {
+            if Some(&path) != curr.as_ref() && !neighbours.contains(&path) {
+                neighbours.push(path);
+            }
+        }
+        neighbours
+    }
+
+    /// Visit the images in a random order, each of them once before any repeats.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffled.clear();
+        if shuffle {
+            self.reshuffle();
+        }
+    }
+
+    pub fn is_shuffled(&self) -> bool {
+        !self.shuffled.is_empty()
+    }
+
+    // Fisher-Yates, the current image goes first so that it is not shown twice in a row
+    fn reshuffle(&mut self) {
+        let curr = self.curr_image_index;
+        let mut order: Vec<usize> = (0..self.len).filter(|index| *index != curr).collect();
+        for i in (1..order.len()).rev() {
+            let j = glib::random_int_range(0, i as i32 + 1) as usize;
+            order.swap(i, j);
+        }
+        order.insert(0, curr);
+        self.shuffled = order;
+        self.shuffle_pos = 0;
+    }
+
+    /// Next image of the slideshow, `None` after the last one unless `looped`.
+    pub fn advance(&mut self, looped: bool) -> Option<PathBuf> {
+        if self.len < 2 {
+            return None;
+        }
+        if self.shuffled.is_empty() {
+            if !looped && self.curr_image_index + 1 == self.len {
+                return None;
+            }
+            return self.next();
+        }
+        if self.shuffle_pos + 1 == self.shuffled.len() {
+            if !looped {
+                return None;
+            }
+            self.reshuffle();
+        }
+        self.shuffle_pos += 1;
+        self.direction = 1;
+        self.curr_image_index = self.shuffled[self.shuffle_pos];
+        self.peek(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(current: &str) -> ImageSet {
+        let images = ["c.png", "a.png", "d.png", "b.png"];
+        ImageSet::new(
+            images.iter().map(PathBuf::from).collect(),
+            &PathBuf::from(current),
+        )
+    }
+
+    fn names(paths: &[PathBuf]) -> Vec<&str> {
+        paths.iter().map(|path| path.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn opens_the_first_image_of_the_folder() {
+        let set = set("a.png");
+        assert_eq!(set.len(), 4);
+        assert_eq!(set.current_index(), 0);
+        assert_eq!(set.peek(0), Some(PathBuf::from("a.png")));
+    }
+
+    #[test]
+    fn unknown_image_gives_an_empty_set() {
+        let mut set = set("z.png");
+        assert!(set.is_empty());
+        assert_eq!(set.next(), None);
+        assert_eq!(set.prev(), None);
+        assert_eq!(set.advance(true), None);
+    }
+
+    #[test]
+    fn next_and_prev_wrap_around() {
+        let mut set = set("d.png");
+        assert_eq!(set.next(), Some(PathBuf::from("a.png")));
+        assert_eq!(set.prev(), Some(PathBuf::from("d.png")));
+        let mut set = self::set("a.png");
+        assert_eq!(set.prev(), Some(PathBuf::from("d.png")));
+        assert_eq!(set.peek(-5), Some(PathBuf::from("c.png")));
+    }
+
+    #[test]
+    fn neighbours_follow_the_direction() {
+        let mut set = set("b.png");
+        assert_eq!(names(&set.neighbours(1)), vec!["c.png", "a.png"]);
+        set.prev();
+        assert_eq!(names(&set.neighbours(1)), vec!["d.png", "b.png"]);
+        // never more than the other images
+        assert_eq!(set.neighbours(10).len(), 3);
+    }
+
+    #[test]
+    fn advance_stops_at_the_end_unless_looped() {
+        let mut set = set("c.png");
+        assert_eq!(set.advance(false), Some(PathBuf::from("d.png")));
+        assert_eq!(set.advance(false), None);
+        assert_eq!(set.advance(true), Some(PathBuf::from("a.png")));
+    }
+
+    #[test]
+    fn shuffle_visits_every_image_once() {
+        let mut set = set("b.png");
+        set.set_shuffle(true);
+        assert!(set.is_shuffled());
+        let mut visited = vec![set.peek(0).unwrap()];
+        while let Some(path) = set.advance(false) {
+            visited.push(path);
+        }
+        visited.sort();
+        assert_eq!(names(&visited), vec!["a.png", "b.png", "c.png", "d.png"]);
+        // a new round doesn't show the last image twice in a row
+        let last = set.peek(0);
+        let next = set.advance(true);
+        assert!(next.is_some() && next != last);
+    }
 }
@@ -0,0 +1,194 @@
+use gdk_pixbuf::Pixbuf;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// What is read from the file itself, on the decode workers.
+#[derive(Debug, Clone, Default)]
+pub struct FileInfo {
+    pub file_size: Option<u64>,
+    pub format: Option<String>,
+}
+
+impl FileInfo {
+    pub fn read(path: &Path) -> Self {
+        let file_size = fs::metadata(path).ok().map(|metadata| metadata.len());
+        // high precision formats are named after their extension, as gdk-pixbuf doesn't know them
+        let format = Pixbuf::get_file_info(path)
+            .and_then(|(format, _, _)| format.get_name().map(String::from))
+            .or_else(|| {
+                let reader = image::io::Reader::open(path)
+                    .ok()?
+                    .with_guessed_format()
+                    .ok()?;
+                reader
+                    .format()?
+                    .extensions_str()
+                    .first()
+                    .map(|ext| ext.to_string())
+            })
+            .map(|name| name.to_uppercase());
+        Self { file_size, format }
+    }
+}
+
+/// What is shown about the current image besides its pixels.
+pub struct ImageInfo {
+    pub path: String,
+    pub name: String,
+    pub width: i32,
+    pub height: i32,
+    pub file_size: Option<u64>,
+    pub format: Option<String>,
+    /// time spent decoding, `None` when the image came from the cache
+    pub load_time: Option<Duration>,
+}
+
+impl ImageInfo {
+    pub fn new(
+        path: &Path,
+        file: FileInfo,
+        width: i32,
+        height: i32,
+        load_time: Option<Duration>,
+    ) -> Self {
+        // paths are shown even when they are not valid UTF-8
+        let name = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        Self {
+            path: path.to_string_lossy().into_owned(),
+            name,
+            width,
+            height,
+            file_size: file.file_size,
+            format: file.format,
+            load_time,
+        }
+    }
+
+    /// Replace the placeholders of `template`:
+    /// `{path}`, `{name}`, `{index}`, `{total}`, `{w}`, `{h}`, `{size}`, `{zoom}`, `{format}` and `{load}`.
+    /// `\n` starts a new line, unknown placeholders are kept as they are.
+    pub fn expand(&self, template: &str, index: usize, total: usize, scale: f64) -> String {
+        // unescaped first, so that a `\n` in a file name is shown as it is
+        let template = template.replace("\\n", "\n");
+        let mut text = String::with_capacity(template.len());
+        let mut rest = template.as_str();
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+            match self.field(&rest[1..end], index, total, scale) {
+                Some(value) => text.push_str(&value),
+                None => text.push_str(&rest[..=end]),
+            }
+            rest = &rest[end + 1..];
+        }
+        text.push_str(rest);
+        text
+    }
+
+    fn field(&self, key: &str, index: usize, total: usize, scale: f64) -> Option<String> {
+        let value = match key {
+            "path" => self.path.clone(),
+            "name" => self.name.clone(),
+            "index" => (index + 1).to_string(),
+            "total" => total.to_string(),
+            "w" => self.width.to_string(),
+            "h" => self.height.to_string(),
+            "size" => self.file_size.map(format_size).unwrap_or_default(),
+            "zoom" => format!("{:.0}", scale * 100.0),
+            "format" => self.format.clone().unwrap_or_default(),
+            "load" => match self.load_time {
+                Some(elapsed) => format!("{} ms", elapsed.as_millis()),
+                None => String::from("cached"),
+            },
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// File size with a binary unit, e.g. `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(load_time: Option<Duration>) -> ImageInfo {
+        ImageInfo {
+            path: String::from("/photos/cat.png"),
+            name: String::from("cat.png"),
+            width: 640,
+            height: 480,
+            file_size: Some(1536),
+            format: Some(String::from("PNG")),
+            load_time,
+        }
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        let info = info(Some(Duration::from_millis(42)));
+        let text = info.expand(
+            "{name} {index}/{total} {w}x{h} {size} {zoom}% {format} {load}",
+            2,
+            9,
+            1.5,
+        );
+        assert_eq!(text, "cat.png 3/9 640x480 1.5 KiB 150% PNG 42 ms");
+        assert_eq!(info.expand("{path}", 0, 1, 1.0), "/photos/cat.png");
+    }
+
+    #[test]
+    fn cached_images_have_no_load_time() {
+        assert_eq!(info(None).expand("{load}", 0, 1, 1.0), "cached");
+    }
+
+    #[test]
+    fn keeps_unknown_placeholders_and_stray_braces() {
+        let info = info(None);
+        assert_eq!(info.expand("{nope} {name}", 0, 1, 1.0), "{nope} cat.png");
+        assert_eq!(info.expand("}{name} {open", 0, 1, 1.0), "}cat.png {open");
+    }
+
+    #[test]
+    fn escaped_newlines_start_lines() {
+        assert_eq!(info(None).expand("{w}\\n{h}", 0, 1, 1.0), "640\n480");
+    }
+
+    #[test]
+    fn newlines_in_names_are_kept_escaped() {
+        let mut info = info(None);
+        info.name = String::from("a\\nb.png");
+        assert_eq!(info.expand("{name}\\n{w}", 0, 1, 1.0), "a\\nb.png\n640");
+    }
+
+    #[test]
+    fn sizes_with_binary_units() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024 + 512 * 1024), "5.5 MiB");
+        assert_eq!(format_size(u64::MAX), "16777216.0 TiB");
+    }
+}
@@ -0,0 +1,130 @@
+use crate::image_handler::hdr::HdrImage;
+use gdk_pixbuf::Pixbuf;
+use std::fmt;
+use std::str::FromStr;
+
+/// Notation a picked colour is copied to the clipboard in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorFormat {
+    /// `#rrggbb`, `#rrggbbaa` for translucent pixels
+    Hex,
+    /// `rgb(r, g, b)`, `rgba(r, g, b, a)` for translucent pixels
+    Rgb,
+    /// channels from 0 to 1, `r, g, b, a`, the original values of high precision images
+    Float,
+}
+
+impl FromStr for ColorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hex" => Ok(ColorFormat::Hex),
+            "rgb" => Ok(ColorFormat::Rgb),
+            "float" => Ok(ColorFormat::Float),
+            _ => Err(format!("unknown color format `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColorFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColorFormat::Hex => "hex",
+            ColorFormat::Rgb => "rgb",
+            ColorFormat::Float => "float",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Colour of one pixel of the decoded original, never of a scaled render.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub x: i32,
+    pub y: i32,
+    pub rgba: [u8; 4],
+    /// values of high precision images before tone mapping
+    pub original: Option<[f32; 4]>,
+}
+
+impl Sample {
+    /// Read the pixel at `x`, `y` of `pixbuf`, `None` outside of the image.
+    pub fn at(pixbuf: &Pixbuf, x: i32, y: i32) -> Option<Self> {
+        if x < 0 || y < 0 || x >= pixbuf.get_width() || y >= pixbuf.get_height() {
+            return None;
+        }
+        // the sub-pixbuf shares the pixels, only the one pixel is read
+        let pixel = pixbuf.new_subpixbuf(x, y, 1, 1)?.read_pixel_bytes()?;
+        let rgba = match (&pixel[..], pixbuf.get_has_alpha()) {
+            ([r, g, b, a, ..], true) => [*r, *g, *b, *a],
+            ([r, g, b, ..], false) => [*r, *g, *b, 255],
+            _ => return None,
+        };
+        Some(Self {
+            x,
+            y,
+            rgba,
+            original: None,
+        })
+    }
+
+    /// Take the original values of a high precision image along.
+    pub fn with_original(mut self, hdr: Option<&HdrImage>) -> Self {
+        self.original = hdr.and_then(|hdr| hdr.pixel(self.x, self.y));
+        self
+    }
+
+    pub fn format(&self, format: ColorFormat) -> String {
+        let [r, g, b, a] = self.rgba;
+        let opaque = a == 255;
+        match format {
+            ColorFormat::Hex if opaque => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            ColorFormat::Hex => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+            ColorFormat::Rgb if opaque => format!("rgb({}, {}, {})", r, g, b),
+            ColorFormat::Rgb => format!("rgba({}, {}, {}, {:.3})", r, g, b, a as f64 / 255.0),
+            ColorFormat::Float => {
+                let [r, g, b, a] = self.original.unwrap_or_else(|| {
+                    let float = |value: u8| value as f32 / 255.0;
+                    [float(r), float(g), float(b), float(a)]
+                });
+                format!("{:.4}, {:.4}, {:.4}, {:.4}", r, g, b, a)
+            }
+        }
+    }
+
+    /// Text shown while inspecting: position and the colour in every notation.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {}\n{}  {}\n{}",
+            self.x,
+            self.y,
+            self.format(ColorFormat::Hex),
+            self.format(ColorFormat::Rgb),
+            self.format(ColorFormat::Float)
+        )
+    }
+}
+
+/// Image pixel under `pos` in the view, for an image of `size` pixels
+/// drawn at `scale` with its top left corner at `origin`.
+/// `None` outside of the view or of the image.
+///
+/// The view only scales and moves the image, so the inverse mapping is all there is to undo.
+pub fn image_xy(
+    pos: [f64; 2],
+    scale: f64,
+    origin: [i32; 2],
+    size: [i32; 2],
+    view: [i32; 2],
+) -> Option<[i32; 2]> {
+    if pos[0] < 0.0 || pos[1] < 0.0 || pos[0] >= view[0] as f64 || pos[1] >= view[1] as f64 {
+        return None;
+    }
+    let x = ((pos[0] - origin[0] as f64) / scale).floor();
+    let y = ((pos[1] - origin[1] as f64) / scale).floor();
+    if x < 0.0 || y < 0.0 || x >= size[0] as f64 || y >= size[1] as f64 {
+        return None;
+    }
+    Some([x as i32, y as i32])
+}
@@ -0,0 +1,64 @@
+use gdk_pixbuf::InterpType;
+use std::fmt;
+
+/// How the image is resampled on screen.
+///
+/// `Auto` previews with nearest neighbour while zooming and refines with `Hyper`
+/// afterwards, above the `nearest_above_zoom` threshold it keeps nearest neighbour
+/// so that every image pixel stays a sharp square. The other modes force
+/// one interpolation type for every render.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Auto,
+    Nearest,
+    Tiles,
+    Bilinear,
+    Hyper,
+}
+
+impl Interpolation {
+    /// The next mode of the manual toggle.
+    pub fn next(self) -> Self {
+        match self {
+            Interpolation::Auto => Interpolation::Nearest,
+            Interpolation::Nearest => Interpolation::Tiles,
+            Interpolation::Tiles => Interpolation::Bilinear,
+            Interpolation::Bilinear => Interpolation::Hyper,
+            Interpolation::Hyper => Interpolation::Auto,
+        }
+    }
+
+    /// Interpolation of the tiles drawn at `scale`.
+    pub fn tiles(self, scale: f64, zooming: bool, nearest_above: f64) -> InterpType {
+        match self {
+            Interpolation::Auto if zooming || scale >= nearest_above => InterpType::Nearest,
+            Interpolation::Auto => InterpType::Bilinear,
+            Interpolation::Nearest => InterpType::Nearest,
+            Interpolation::Tiles => InterpType::Tiles,
+            Interpolation::Bilinear => InterpType::Bilinear,
+            Interpolation::Hyper => InterpType::Hyper,
+        }
+    }
+
+    /// Interpolation of the background render replacing the tiles once the view settles,
+    /// `None` when the tiles are already final.
+    pub fn refine(self, scale: f64, nearest_above: f64) -> Option<InterpType> {
+        match self {
+            Interpolation::Auto if scale < nearest_above => Some(InterpType::Hyper),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Interpolation::Auto => "auto",
+            Interpolation::Nearest => "nearest",
+            Interpolation::Tiles => "tiles",
+            Interpolation::Bilinear => "bilinear",
+            Interpolation::Hyper => "hyper",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,307 @@
+use crate::image_handler::color::ColorManager;
+use crate::image_handler::hdr::{self, HdrImage, ToneMap};
+use crate::image_handler::info::FileInfo;
+use crate::image_handler::window_level::Window;
+use gdk_pixbuf::{Colorspace, Pixbuf, PixbufLoader, PixbufLoaderExt};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// size of the chunks fed to the pixbuf loader, cancellation is checked between chunks
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Raw pixels of a decoded image.
+/// `gdk_pixbuf::Pixbuf` is not `Send`, so workers hand back the pixel bytes
+/// and the pixbuf is rebuilt on the GTK main thread without copying.
+pub struct PixelData {
+    bytes: glib::Bytes,
+    has_alpha: bool,
+    bits_per_sample: i32,
+    width: i32,
+    height: i32,
+    rowstride: i32,
+}
+
+impl PixelData {
+    pub fn from_pixbuf(pixbuf: &Pixbuf) -> Option<Self> {
+        Some(Self {
+            bytes: pixbuf.read_pixel_bytes()?,
+            has_alpha: pixbuf.get_has_alpha(),
+            bits_per_sample: pixbuf.get_bits_per_sample(),
+            width: pixbuf.get_width(),
+            height: pixbuf.get_height(),
+            rowstride: pixbuf.get_rowstride(),
+        })
+    }
+
+    /// Wrap tightly packed 8 bit RGBA pixels.
+    pub fn from_rgba(width: i32, height: i32, pixels: Vec<u8>) -> Self {
+        Self {
+            bytes: glib::Bytes::from_owned(pixels),
+            has_alpha: true,
+            bits_per_sample: 8,
+            width,
+            height,
+            rowstride: width * 4,
+        }
+    }
+
+    pub fn to_pixbuf(&self) -> Pixbuf {
+        Pixbuf::from_bytes(
+            &self.bytes,
+            Colorspace::Rgb,
+            self.has_alpha,
+            self.bits_per_sample,
+            self.width,
+            self.height,
+            self.rowstride,
+        )
+    }
+}
+
+/// Result of a decode request, delivered to the main loop through a glib channel.
+pub struct DecodedImage {
+    pub path: PathBuf,
+    pub generation: usize,
+    /// decoded ahead of navigation, goes to the cache instead of the screen
+    pub prefetch: bool,
+    /// time spent reading and decoding the file
+    pub elapsed: Duration,
+    /// colour management was on when it was decoded
+    pub color_enabled: bool,
+    /// colours were converted for the display
+    pub color_managed: bool,
+    /// full precision pixels the result was rendered from
+    pub hdr: Option<Arc<HdrImage>>,
+    /// window chosen for a grayscale image, when they are windowed automatically
+    pub window: Option<Window>,
+    /// size and format of the file, shown in the info overlay
+    pub file: FileInfo,
+    pub result: Result<PixelData, String>,
+}
+
+struct Job {
+    path: PathBuf,
+    generation: usize,
+    prefetch: bool,
+}
+
+/// Pool of worker threads decoding images off the GTK main thread.
+///
+/// Every `request` bumps the generation counter, jobs and results of older
+/// generations are stale: workers skip or abort them and the receiver drops them.
+pub struct Loader {
+    jobs: mpsc::Sender<Job>,
+    generation: Arc<AtomicUsize>,
+}
+
+impl Loader {
+    pub fn new(
+        workers: usize,
+        results: glib::Sender<DecodedImage>,
+        color: Arc<ColorManager>,
+        tone_map: Arc<Mutex<ToneMap>>,
+        auto_window: Option<[f32; 2]>,
+    ) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        let generation = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..workers.max(1) {
+            let queue = Arc::clone(&queue);
+            let generation = Arc::clone(&generation);
+            let results = results.clone();
+            let color = Arc::clone(&color);
+            let tone_map = Arc::clone(&tone_map);
+            thread::spawn(move || loop {
+                let job = match queue.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    // loader was dropped
+                    Err(_) => return,
+                };
+                if job.generation != generation.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let started = Instant::now();
+                let color_enabled = color.is_enabled();
+                let color = if color_enabled {
+                    Some(color.as_ref())
+                } else {
+                    None
+                };
+                let cancelled = || job.generation != generation.load(Ordering::SeqCst);
+                let (result, color_managed, hdr, window) = if hdr::is_high_precision(&job.path) {
+                    // full precision pixels are too big to decode ahead for the cache
+                    if job.prefetch {
+                        continue;
+                    }
+                    let tone_map = *tone_map.lock().unwrap();
+                    match decode_hdr(&job.path, color, tone_map, auto_window, cancelled) {
+                        Some(Ok((pixels, managed, image, window))) => {
+                            (Ok(pixels), managed, Some(image), window)
+                        }
+                        Some(Err(err)) => (Err(err), false, None, None),
+                        None => continue,
+                    }
+                } else {
+                    match decode(&job.path, color, cancelled) {
+                        Some(Ok((pixels, managed))) => (Ok(pixels), managed, None, None),
+                        Some(Err(err)) => (Err(err), false, None, None),
+                        None => continue,
+                    }
+                };
+                let elapsed = started.elapsed();
+                let file = FileInfo::read(&job.path);
+                let decoded = DecodedImage {
+                    path: job.path,
+                    generation: job.generation,
+                    prefetch: job.prefetch,
+                    elapsed,
+                    color_enabled,
+                    color_managed,
+                    hdr,
+                    window,
+                    file,
+                    result,
+                };
+                if results.send(decoded).is_err() {
+                    return;
+                }
+            });
+        }
+
+        Self { jobs, generation }
+    }
+
+    /// Queue `path` for decoding, cancelling every request made before it.
+    /// Returns the generation the result will be tagged with.
+    pub fn request(&self, path: PathBuf) -> usize {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.jobs
+            .send(Job {
+                path,
+                generation,
+                prefetch: false,
+            })
+            .unwrap();
+        generation
+    }
+
+    /// Queue `path` for decoding into the cache,
+    /// it is cancelled together with the current request by the next `request`.
+    pub fn prefetch(&self, path: PathBuf) {
+        let generation = self.generation.load(Ordering::SeqCst);
+        self.jobs
+            .send(Job {
+                path,
+                generation,
+                prefetch: true,
+            })
+            .unwrap();
+    }
+
+    /// Cancel every pending request and prefetch.
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn is_current(&self, generation: usize) -> bool {
+        generation == self.generation.load(Ordering::SeqCst)
+    }
+}
+
+/// Decode `path` chunk by chunk and convert its colours with `color`, returns the
+/// pixels and whether they were converted or `None` once `cancelled` reports true.
+fn decode<F: Fn() -> bool>(
+    path: &PathBuf,
+    color: Option<&ColorManager>,
+    cancelled: F,
+) -> Option<Result<(PixelData, bool), String>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => return Some(Err(err.to_string())),
+    };
+    let loader = PixbufLoader::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        if cancelled() {
+            loader.close().ok();
+            return None;
+        }
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) => {
+                loader.close().ok();
+                return Some(Err(err.to_string()));
+            }
+        };
+        if let Err(err) = loader.write(&buf[..n]) {
+            loader.close().ok();
+            return Some(Err(err.to_string()));
+        }
+    }
+    if let Err(err) = loader.close() {
+        return Some(Err(err.to_string()));
+    }
+    let pixbuf = match loader.get_pixbuf() {
+        Some(pb) => pb,
+        None => return Some(Err(String::from("no image data"))),
+    };
+    let managed = match color.map(|color| color.convert(path, &pixbuf)) {
+        Some(Ok(managed)) => managed,
+        Some(Err(err)) => {
+            eprintln!("showing unmanaged colors of {:?}: {}", path, err);
+            false
+        }
+        None => false,
+    };
+    match PixelData::from_pixbuf(&pixbuf) {
+        Some(pixels) => Some(Ok((pixels, managed))),
+        None => Some(Err(String::from("no pixel data"))),
+    }
+}
+
+// rendered pixels, whether their colours were converted, full precision pixels
+// and the automatic window of grayscale images
+type HdrDecoded = (PixelData, bool, Arc<HdrImage>, Option<Window>);
+
+/// Decode `path` at full precision, convert its colours with `color` and render it
+/// with `tone_map`, grayscale images with the window spanning the `auto_window`
+/// percentiles when given. Returns `None` once `cancelled` reports true.
+fn decode_hdr<F: Fn() -> bool>(
+    path: &Path,
+    color: Option<&ColorManager>,
+    mut tone_map: ToneMap,
+    auto_window: Option<[f32; 2]>,
+    cancelled: F,
+) -> Option<Result<HdrDecoded, String>> {
+    let mut image = match HdrImage::open(path) {
+        Ok(image) => image,
+        Err(err) => return Some(Err(err)),
+    };
+    let managed = match color.map(|color| image.convert_colors(path, color)) {
+        Some(Ok(managed)) => managed,
+        Some(Err(err)) => {
+            eprintln!("showing unmanaged colors of {:?}: {}", path, err);
+            false
+        }
+        None => false,
+    };
+    let window = match auto_window {
+        // a kept window wins over the automatic one
+        Some(_) if tone_map.keep_window && tone_map.window.is_some() => None,
+        Some(percentiles) if image.grayscale => Some(image.auto_window(percentiles)),
+        _ => None,
+    };
+    if window.is_some() {
+        tone_map.window = window;
+    }
+    let pixels = image.render(tone_map, cancelled)?;
+    Some(Ok((pixels, managed, Arc::new(image), window)))
+}
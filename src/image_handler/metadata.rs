@@ -0,0 +1,574 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// TIFF tags carrying the other standards inside the EXIF data
+const TIFF_XMP: u16 = 700;
+const TIFF_IPTC: u16 = 33723;
+// longest value shown, binary blobs such as the maker note are cut
+const MAX_VALUE_LEN: usize = 256;
+// larger PNG text chunks are skipped instead of read, the length is up to the file
+const MAX_CHUNK_LEN: u64 = 16 * 1024 * 1024;
+
+/// Metadata standard a field comes from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Group {
+    Exif,
+    Iptc,
+    Xmp,
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Group::Exif => "EXIF",
+            Group::Iptc => "IPTC",
+            Group::Xmp => "XMP",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub group: Group,
+    pub name: String,
+    pub value: String,
+}
+
+impl Field {
+    fn new(group: Group, name: &str, value: String) -> Self {
+        Self {
+            group,
+            name: String::from(name),
+            value,
+        }
+    }
+
+    /// Cut a long value, once repeated datasets and list items were joined.
+    fn truncate(&mut self) {
+        if let Some((end, _)) = self.value.char_indices().nth(MAX_VALUE_LEN) {
+            self.value.truncate(end);
+            self.value.push('…');
+        }
+    }
+}
+
+/// EXIF, IPTC and XMP fields of the image at `path`, an image without metadata has none.
+/// Only fields of the primary image are listed, not those of the embedded thumbnail.
+pub fn read(path: &Path) -> Result<Vec<Field>, String> {
+    let mut file = BufReader::new(File::open(path).map_err(|err| err.to_string())?);
+    let mut fields = Vec::new();
+    let mut iptc = Vec::new();
+    let mut xmp = Vec::new();
+
+    if let Ok(exif) = exif::Reader::new().read_from_container(&mut file) {
+        for field in exif.fields() {
+            if field.ifd_num != exif::In::PRIMARY {
+                continue;
+            }
+            match (field.tag.number(), &field.value) {
+                (TIFF_XMP, exif::Value::Byte(bytes))
+                | (TIFF_XMP, exif::Value::Undefined(bytes, _)) => xmp = bytes.clone(),
+                (TIFF_IPTC, exif::Value::Byte(bytes))
+                | (TIFF_IPTC, exif::Value::Undefined(bytes, _)) => iptc = bytes.clone(),
+                (TIFF_IPTC, exif::Value::Long(longs)) => {
+                    iptc = long_bytes(longs, exif.little_endian())
+                }
+                _ => {
+                    let value = field.display_value().with_unit(&exif).to_string();
+                    // text is shown without the quotes
+                    let value = match &field.value {
+                        exif::Value::Ascii(_) => value.trim_matches('"').to_string(),
+                        _ => value,
+                    };
+                    fields.push(Field::new(Group::Exif, &field.tag.to_string(), value));
+                }
+            }
+        }
+        if let Some(position) = gps_position(&exif) {
+            fields.push(Field::new(Group::Exif, "GPSPosition", position));
+        }
+    }
+
+    // JPEG and PNG keep IPTC and XMP outside of the EXIF data
+    file.seek(SeekFrom::Start(0))
+        .map_err(|err| err.to_string())?;
+    let mut magic = [0u8; 8];
+    if file.read_exact(&mut magic).is_ok() {
+        file.seek(SeekFrom::Start(0))
+            .map_err(|err| err.to_string())?;
+        if magic.starts_with(&[0xff, 0xd8]) {
+            // a truncated file still shows the metadata read so far
+            read_jpeg_segments(&mut file, &mut iptc, &mut xmp).ok();
+        } else if magic == *b"\x89PNG\r\n\x1a\n" {
+            read_png_chunks(&mut file, &mut xmp).ok();
+        }
+    }
+
+    fields.extend(parse_iptc(&iptc));
+    fields.extend(parse_xmp(&String::from_utf8_lossy(&xmp)));
+    fields.iter_mut().for_each(Field::truncate);
+    Ok(fields)
+}
+
+/// The bytes of a field some writers store as LONGs, in the byte order of the TIFF file.
+fn long_bytes(longs: &[u32], little_endian: bool) -> Vec<u8> {
+    longs
+        .iter()
+        .flat_map(|long| {
+            if little_endian {
+                long.to_le_bytes()
+            } else {
+                long.to_be_bytes()
+            }
+        })
+        .collect()
+}
+
+/// Signed decimal degrees of the GPS coordinates, e.g. `50.450100, 30.523400`.
+fn gps_position(exif: &exif::Exif) -> Option<String> {
+    let degrees = |tag, ref_tag, negative: &[u8]| -> Option<f64> {
+        let field = exif.get_field(tag, exif::In::PRIMARY)?;
+        let dms = match &field.value {
+            exif::Value::Rational(dms) if dms.len() == 3 => dms,
+            _ => return None,
+        };
+        let degrees = dms[0].to_f64() + dms[1].to_f64() / 60.0 + dms[2].to_f64() / 3600.0;
+        let sign = match exif.get_field(ref_tag, exif::In::PRIMARY).map(|f| &f.value) {
+            Some(exif::Value::Ascii(refs)) if refs.first().map(|r| &r[..]) == Some(negative) => {
+                -1.0
+            }
+            _ => 1.0,
+        };
+        Some(sign * degrees)
+    };
+    let latitude = degrees(exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef, b"S")?;
+    let longitude = degrees(exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef, b"W")?;
+    Some(format!("{:.6}, {:.6}", latitude, longitude))
+}
+
+/// Collect the IPTC (Photoshop APP13) and XMP (APP1) segments of a JPEG.
+fn read_jpeg_segments<R: Read + Seek>(
+    file: &mut R,
+    iptc: &mut Vec<u8>,
+    xmp: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    const XMP_ID: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+    const PHOTOSHOP_ID: &[u8] = b"Photoshop 3.0\0";
+
+    file.seek(SeekFrom::Start(2))?;
+    loop {
+        let mut marker = [0u8; 4];
+        file.read_exact(&mut marker)?;
+        // start of scan, the metadata comes before the image data
+        if marker[0] != 0xff || marker[1] == 0xda || marker[1] == 0xd9 {
+            return Ok(());
+        }
+        let len = u16::from_be_bytes([marker[2], marker[3]]) as usize;
+        if len < 2 {
+            return Ok(());
+        }
+        match marker[1] {
+            0xe1 | 0xed => {
+                let mut segment = vec![0u8; len - 2];
+                file.read_exact(&mut segment)?;
+                if marker[1] == 0xe1 && segment.starts_with(XMP_ID) && xmp.is_empty() {
+                    *xmp = segment[XMP_ID.len()..].to_vec();
+                } else if marker[1] == 0xed && segment.starts_with(PHOTOSHOP_ID) {
+                    iptc.extend(photoshop_iptc(&segment[PHOTOSHOP_ID.len()..]));
+                }
+            }
+            _ => {
+                file.seek(SeekFrom::Current(len as i64 - 2))?;
+            }
+        }
+    }
+}
+
+/// IPTC data from the image resource blocks of a Photoshop segment.
+fn photoshop_iptc(mut data: &[u8]) -> Vec<u8> {
+    const IPTC_RESOURCE: u16 = 0x0404;
+
+    let mut iptc = Vec::new();
+    while data.len() >= 12 && data.starts_with(b"8BIM") {
+        let id = u16::from_be_bytes([data[4], data[5]]);
+        // the name is a pascal string padded to an even length
+        let name_len = (data[6] as usize + 2) & !1;
+        let header = 6 + name_len + 4;
+        if data.len() < header {
+            break;
+        }
+        let size = u32::from_be_bytes([
+            data[header - 4],
+            data[header - 3],
+            data[header - 2],
+            data[header - 1],
+        ]) as usize;
+        let end = (header + size).min(data.len());
+        if id == IPTC_RESOURCE {
+            iptc.extend_from_slice(&data[header..end]);
+        }
+        data = &data[(end + (size & 1)).min(data.len())..];
+    }
+    iptc
+}
+
+/// Collect the XMP packet of a PNG from its `iTXt` chunk.
+fn read_png_chunks<R: Read + Seek>(file: &mut R, xmp: &mut Vec<u8>) -> std::io::Result<()> {
+    const XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+    file.seek(SeekFrom::Start(8))?;
+    loop {
+        let mut header = [0u8; 8];
+        if file.read_exact(&mut header).is_err() {
+            return Ok(());
+        }
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        match &header[4..] {
+            b"IEND" => return Ok(()),
+            b"iTXt" if len <= MAX_CHUNK_LEN => {
+                let mut chunk = vec![0u8; len as usize];
+                file.read_exact(&mut chunk)?;
+                // keyword, compression flag and method, empty language tag and keyword
+                if chunk.starts_with(XMP_KEYWORD) && chunk.get(XMP_KEYWORD.len()) == Some(&0) {
+                    let text = chunk
+                        .get(XMP_KEYWORD.len() + 2..)
+                        .unwrap_or(&[])
+                        .splitn(3, |&b| b == 0)
+                        .nth(2)
+                        .unwrap_or(&[]);
+                    *xmp = text.to_vec();
+                }
+                file.seek(SeekFrom::Current(4))?;
+            }
+            _ => {
+                file.seek(SeekFrom::Current(len as i64 + 4))?;
+            }
+        }
+    }
+}
+
+/// Fields of the IPTC-IIM application record, repeated datasets such as keywords are joined.
+fn parse_iptc(mut data: &[u8]) -> Vec<Field> {
+    let mut fields: Vec<Field> = Vec::new();
+    while data.len() >= 5 && data[0] == 0x1c {
+        let (record, dataset) = (data[1], data[2]);
+        let size = u16::from_be_bytes([data[3], data[4]]) as usize;
+        // extended datasets are larger than any text field
+        if size & 0x8000 != 0 || data.len() < 5 + size {
+            break;
+        }
+        let value = String::from_utf8_lossy(&data[5..5 + size])
+            .trim()
+            .to_string();
+        data = &data[5 + size..];
+        if record != 2 || dataset == 0 {
+            continue;
+        }
+        let name = iptc_name(dataset)
+            .map(String::from)
+            .unwrap_or_else(|| format!("2:{}", dataset));
+        match fields.iter_mut().find(|field| field.name == name) {
+            Some(field) => {
+                field.value.push_str(", ");
+                field.value.push_str(&value);
+            }
+            None => fields.push(Field::new(Group::Iptc, &name, value)),
+        }
+    }
+    fields
+}
+
+fn iptc_name(dataset: u8) -> Option<&'static str> {
+    let name = match dataset {
+        5 => "ObjectName",
+        10 => "Urgency",
+        15 => "Category",
+        20 => "SupplementalCategories",
+        25 => "Keywords",
+        40 => "SpecialInstructions",
+        55 => "DateCreated",
+        60 => "TimeCreated",
+        80 => "Byline",
+        85 => "BylineTitle",
+        90 => "City",
+        92 => "Sublocation",
+        95 => "ProvinceState",
+        100 => "CountryCode",
+        101 => "Country",
+        103 => "OriginalTransmissionReference",
+        105 => "Headline",
+        110 => "Credit",
+        115 => "Source",
+        116 => "CopyrightNotice",
+        120 => "Caption",
+        122 => "Writer",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Properties of an XMP packet, from the attributes of `rdf:Description` and from
+/// the text of property elements. Items of `rdf:Bag`, `rdf:Seq` and `rdf:Alt` are joined.
+fn parse_xmp(xml: &str) -> Vec<Field> {
+    let mut fields: Vec<Field> = Vec::new();
+    let mut add = |name: &str, value: String| {
+        if value.is_empty() {
+            return;
+        }
+        match fields.iter_mut().find(|field| field.name == name) {
+            Some(field) => {
+                field.value.push_str(", ");
+                field.value.push_str(&value);
+            }
+            None => fields.push(Field::new(Group::Xmp, name, value)),
+        }
+    };
+
+    // open property elements, innermost last
+    let mut elements: Vec<String> = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        let text = rest[..start].trim();
+        if !text.is_empty() {
+            if let Some(name) = elements.iter().rev().find(|name| !is_syntax(name)) {
+                add(name, unescape(text));
+            }
+        }
+        rest = &rest[start..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            if let Some(pos) = elements.iter().rposition(|open| open == name.trim()) {
+                elements.truncate(pos);
+            }
+            continue;
+        }
+        let empty = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let mut parts = tag.splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or("").to_string();
+        // shorthand properties are attributes of the description
+        for (key, value) in attributes(parts.next().unwrap_or("")) {
+            if key.contains(':') && !key.starts_with("xmlns") && !is_syntax(key) {
+                add(key, unescape(value));
+            }
+        }
+        if !empty {
+            elements.push(name);
+        }
+    }
+    fields
+}
+
+/// Names of the RDF and XMP packet structure, as opposed to properties.
+fn is_syntax(name: &str) -> bool {
+    name.starts_with("rdf:") || name.starts_with("x:") || name.starts_with("xml:")
+}
+
+/// `key="value"` pairs of a tag, single or double quoted.
+fn attributes(mut s: &str) -> Vec<(&str, &str)> {
+    let mut pairs = Vec::new();
+    while let Some(eq) = s.find('=') {
+        let key = s[..eq].trim();
+        let after = s[eq + 1..].trim_start();
+        let quote = match after.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => break,
+        };
+        let value_end = match after[1..].find(quote) {
+            Some(end) => end + 1,
+            None => break,
+        };
+        pairs.push((key, &after[1..value_end]));
+        s = &after[value_end + 1..];
+    }
+    pairs
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#xA;", "\n")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const XMP: &[u8] =
+        br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF><rdf:Description xmp:Rating="4">
+<dc:subject><rdf:Bag><rdf:li>cat</rdf:li><rdf:li>garden &amp; sun</rdf:li></rdf:Bag></dc:subject>
+</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+    // IPTC datasets: a keyword twice and a city
+    const IPTC: &[u8] = b"\x1c\x02\x19\x00\x03cat\x1c\x02\x19\x00\x03dog\x1c\x02\x5a\x00\x04Kyiv";
+
+    fn png(chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, data) in chunks {
+            png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            png.extend_from_slice(kind);
+            png.extend_from_slice(data);
+            // CRC, not checked
+            png.extend_from_slice(&[0; 4]);
+        }
+        png
+    }
+
+    fn xmp_itxt(packet: &[u8]) -> Vec<u8> {
+        // keyword, no compression, empty language tag and translated keyword
+        [b"XML:com.adobe.xmp\0\0\0\0\0", packet].concat()
+    }
+
+    fn values(fields: &[Field]) -> Vec<(&str, &str)> {
+        fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn png_xmp_packet() {
+        let itxt = xmp_itxt(XMP);
+        let file = png(&[(b"IHDR", &[0; 13]), (b"iTXt", &itxt), (b"IEND", &[])]);
+        let mut xmp = Vec::new();
+        read_png_chunks(&mut Cursor::new(file), &mut xmp).unwrap();
+        assert_eq!(xmp, XMP);
+    }
+
+    #[test]
+    fn png_without_xmp_packet() {
+        let file = png(&[
+            (b"IHDR", &[0; 13]),
+            (b"tEXt", b"Title\0cat"),
+            (b"IEND", &[]),
+        ]);
+        let mut xmp = Vec::new();
+        read_png_chunks(&mut Cursor::new(file), &mut xmp).unwrap();
+        assert!(xmp.is_empty());
+        assert!(parse_xmp(&String::from_utf8_lossy(&xmp)).is_empty());
+    }
+
+    #[test]
+    fn png_itxt_ending_after_keyword() {
+        let file = png(&[(b"iTXt", b"XML:com.adobe.xmp\0\0"), (b"IEND", &[])]);
+        let mut xmp = Vec::new();
+        read_png_chunks(&mut Cursor::new(file), &mut xmp).unwrap();
+        assert!(xmp.is_empty());
+    }
+
+    #[test]
+    fn png_truncated_chunk() {
+        let mut file = png(&[(b"iTXt", &xmp_itxt(XMP))]);
+        file.truncate(40);
+        let mut xmp = Vec::new();
+        assert!(read_png_chunks(&mut Cursor::new(file), &mut xmp).is_err());
+        assert!(xmp.is_empty());
+    }
+
+    #[test]
+    fn png_oversized_chunk_is_skipped() {
+        let mut file = b"\x89PNG\r\n\x1a\n".to_vec();
+        file.extend_from_slice(&u32::MAX.to_be_bytes());
+        file.extend_from_slice(b"iTXt");
+        let mut xmp = Vec::new();
+        read_png_chunks(&mut Cursor::new(file), &mut xmp).unwrap();
+        assert!(xmp.is_empty());
+    }
+
+    #[test]
+    fn jpeg_xmp_and_iptc_segments() {
+        let app1 = [&b"http://ns.adobe.com/xap/1.0/\0"[..], XMP].concat();
+        let mut resource = b"Photoshop 3.0\08BIM\x04\x04\0\0".to_vec();
+        resource.extend_from_slice(&(IPTC.len() as u32).to_be_bytes());
+        resource.extend_from_slice(IPTC);
+        let mut file = vec![0xff, 0xd8];
+        for (marker, data) in [(0xe1, &app1), (0xed, &resource)] {
+            file.extend_from_slice(&[0xff, marker]);
+            file.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+            file.extend_from_slice(data);
+        }
+        file.extend_from_slice(&[0xff, 0xda, 0, 2]);
+
+        let (mut iptc, mut xmp) = (Vec::new(), Vec::new());
+        read_jpeg_segments(&mut Cursor::new(file), &mut iptc, &mut xmp).unwrap();
+        assert_eq!(xmp, XMP);
+        assert_eq!(iptc, IPTC);
+    }
+
+    #[test]
+    fn jpeg_truncated_segment() {
+        let file = vec![0xff, 0xd8, 0xff, 0xe1, 0x01, 0x00, b'h', b't'];
+        let (mut iptc, mut xmp) = (Vec::new(), Vec::new());
+        assert!(read_jpeg_segments(&mut Cursor::new(file), &mut iptc, &mut xmp).is_err());
+        assert!(iptc.is_empty() && xmp.is_empty());
+    }
+
+    #[test]
+    fn iptc_fields() {
+        let fields = parse_iptc(IPTC);
+        assert_eq!(
+            values(&fields),
+            vec![("Keywords", "cat, dog"), ("City", "Kyiv")]
+        );
+    }
+
+    #[test]
+    fn joined_values_are_truncated_once() {
+        let iptc = b"\x1c\x02\x19\x00\x09keyword--".repeat(40);
+        let mut fields = parse_iptc(&iptc);
+        fields[0].truncate();
+        assert_eq!(fields[0].value.chars().count(), MAX_VALUE_LEN + 1);
+        assert_eq!(fields[0].value.matches('…').count(), 1);
+        assert!(fields[0].value.ends_with('…'));
+    }
+
+    #[test]
+    fn iptc_truncated_dataset() {
+        let fields = parse_iptc(&IPTC[..IPTC.len() - 2]);
+        assert_eq!(values(&fields), vec![("Keywords", "cat, dog")]);
+    }
+
+    #[test]
+    fn iptc_longs_in_both_byte_orders() {
+        // LONGs hold the IPTC bytes as they are in the file, padded to a multiple of 4
+        let mut padded = IPTC.to_vec();
+        padded.resize(IPTC.len().div_ceil(4) * 4, 0);
+        let longs = |read: fn([u8; 4]) -> u32| -> Vec<u32> {
+            padded
+                .chunks_exact(4)
+                .map(|b| read([b[0], b[1], b[2], b[3]]))
+                .collect()
+        };
+        assert_eq!(long_bytes(&longs(u32::from_be_bytes), false), padded);
+        assert_eq!(long_bytes(&longs(u32::from_le_bytes), true), padded);
+        let fields = parse_iptc(&long_bytes(&longs(u32::from_le_bytes), true));
+        assert_eq!(
+            values(&fields),
+            vec![("Keywords", "cat, dog"), ("City", "Kyiv")]
+        );
+    }
+
+    #[test]
+    fn xmp_properties() {
+        let fields = parse_xmp(&String::from_utf8_lossy(XMP));
+        assert_eq!(
+            values(&fields),
+            vec![("xmp:Rating", "4"), ("dc:subject", "cat, garden & sun")]
+        );
+    }
+}
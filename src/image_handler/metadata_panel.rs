@@ -0,0 +1,206 @@
+use crate::image_handler::metadata::{self, Field};
+use glib::Continue;
+use gtk::prelude::GtkListStoreExtManual;
+use gtk::{
+    EntryExt, GtkListStoreExt, SearchEntryExt, TreeModelExt, TreeModelFilterExt, TreeSelectionExt,
+    TreeViewExt, WidgetExt,
+};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+
+// columns of the list store
+const GROUP: u32 = 0;
+const NAME: u32 = 1;
+const VALUE: u32 = 2;
+
+struct ReadMetadata {
+    generation: usize,
+    path: PathBuf,
+    result: Result<Vec<Field>, String>,
+}
+
+/// Side panel listing the EXIF, IPTC and XMP fields of the current image.
+///
+/// Metadata is read off the main thread and only while the panel is visible,
+/// the fields can be filtered and copied to the clipboard.
+pub struct MetadataPanel {
+    panel: gtk::Box,
+    view: gtk::TreeView,
+    store: gtk::ListStore,
+    filter: gtk::TreeModelFilter,
+    filter_entry: gtk::SearchEntry,
+    // image the panel is showing or reading
+    current: Rc<RefCell<Option<PathBuf>>>,
+    // path of the listed fields, set once they were read
+    listed: Rc<RefCell<Option<PathBuf>>>,
+    // bumped by every read, older reads are stale
+    generation: Rc<RefCell<usize>>,
+    sender: glib::Sender<ReadMetadata>,
+    receiver: Option<glib::Receiver<ReadMetadata>>,
+}
+
+impl MetadataPanel {
+    pub fn new(panel: gtk::Box, view: gtk::TreeView, filter_entry: gtk::SearchEntry) -> Self {
+        let store =
+            gtk::ListStore::new(&[glib::Type::String, glib::Type::String, glib::Type::String]);
+        let filter = gtk::TreeModelFilter::new(&store, None);
+        view.set_model(Some(&filter));
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        Self {
+            panel,
+            view,
+            store,
+            filter,
+            filter_entry,
+            current: Rc::new(RefCell::new(None)),
+            listed: Rc::new(RefCell::new(None)),
+            generation: Rc::new(RefCell::new(0)),
+            sender,
+            receiver: Some(receiver),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.panel.get_visible()
+    }
+
+    pub fn set_visible(&self, visible: bool) {
+        self.panel.set_visible(visible);
+        if visible {
+            self.refresh();
+        }
+    }
+
+    /// Width taken from the image view, 0 when hidden.
+    pub fn width(&self) -> i32 {
+        if self.is_visible() {
+            self.panel.get_size_request().0
+        } else {
+            0
+        }
+    }
+
+    /// List the metadata of `path`, it is read once the panel is visible.
+    pub fn show(&self, path: &Path) {
+        *self.current.borrow_mut() = Some(path.to_path_buf());
+        if self.is_visible() {
+            self.refresh();
+        }
+    }
+
+    fn refresh(&self) {
+        let path = match self.current.borrow().clone() {
+            Some(path) => path,
+            None => return,
+        };
+        if self.listed.borrow().as_ref() == Some(&path) {
+            return;
+        }
+        // a failed read is tried again the next time the image is shown
+        *self.listed.borrow_mut() = None;
+        self.store.clear();
+        *self.generation.borrow_mut() += 1;
+        let generation = *self.generation.borrow();
+        let sender = self.sender.clone();
+        thread::spawn(move || {
+            let result = metadata::read(&path);
+            sender
+                .send(ReadMetadata {
+                    generation,
+                    path,
+                    result,
+                })
+                .ok();
+        });
+    }
+
+    /// Copy the selected fields as `name: value` lines.
+    pub fn copy_selection(&self) {
+        let (rows, model) = self.view.get_selection().get_selected_rows();
+        let lines: Vec<String> = rows
+            .iter()
+            .filter_map(|row| model.get_iter(row))
+            .map(|iter| {
+                let name = get_string(&model, &iter, NAME);
+                let value = get_string(&model, &iter, VALUE);
+                format!("{}: {}", name, value)
+            })
+            .collect();
+        if !lines.is_empty() {
+            copy_to_clipboard(&self.view, &lines.join("\n"));
+        }
+    }
+
+    pub fn init_events(&mut self) {
+        // fill the list with the fields of the current image, stale reads are dropped
+        let store = self.store.clone();
+        let generation = self.generation.clone();
+        let listed = self.listed.clone();
+        let receiver = self.receiver.take().expect("init_events called twice");
+        receiver.attach(None, move |read| {
+            if read.generation != *generation.borrow() {
+                return Continue(true);
+            }
+            let fields = match read.result {
+                Ok(fields) => fields,
+                Err(err) => {
+                    eprintln!("can't read metadata of {:?}: {}", read.path, err);
+                    return Continue(true);
+                }
+            };
+            *listed.borrow_mut() = Some(read.path);
+            for field in fields {
+                store.insert_with_values(
+                    None,
+                    &[GROUP, NAME, VALUE],
+                    &[&field.group.to_string(), &field.name, &field.value],
+                );
+            }
+            Continue(true)
+        });
+
+        // match the filter against every column, ignoring case
+        let filter_entry = self.filter_entry.clone();
+        self.filter.set_visible_func(move |model, iter| {
+            let text = filter_entry.get_text().to_lowercase();
+            if text.is_empty() {
+                return true;
+            }
+            [GROUP, NAME, VALUE].iter().any(|&column| {
+                get_string(model, iter, column)
+                    .to_lowercase()
+                    .contains(&text)
+            })
+        });
+        let filter = self.filter.clone();
+        self.filter_entry
+            .connect_search_changed(move |_entry| filter.refilter());
+
+        // a double click copies the value
+        self.view.connect_row_activated(move |view, row, _column| {
+            let model = match view.get_model() {
+                Some(model) => model,
+                None => return,
+            };
+            if let Some(iter) = model.get_iter(row) {
+                copy_to_clipboard(view, &get_string(&model, &iter, VALUE));
+            }
+        });
+    }
+}
+
+fn get_string(model: &gtk::TreeModel, iter: &gtk::TreeIter, column: u32) -> String {
+    model
+        .get_value(iter, column as i32)
+        .get::<String>()
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+fn copy_to_clipboard(widget: &gtk::TreeView, text: &str) {
+    let clipboard = widget.get_clipboard(&gdk::SELECTION_CLIPBOARD);
+    clipboard.set_text(text);
+}
@@ -1,4 +1,33 @@
+pub mod animation;
+pub mod backdrop;
+pub mod cache;
+pub mod color;
 pub mod controller;
+pub mod filmstrip;
+pub mod gallery;
+pub mod hdr;
+pub mod histogram;
 pub mod image_set;
+pub mod info;
+pub mod inspector;
+pub mod interpolation;
+pub mod loader;
+pub mod metadata;
+pub mod metadata_panel;
+pub mod overlay;
+pub mod probe;
+pub mod refine;
+pub mod slideshow;
+pub mod source;
+pub mod thumbnail_cache;
+pub mod thumbnails;
+pub mod tiles;
+pub mod transition;
+pub mod window_level;
+pub mod zoom;
 pub use self::controller::Controller;
+pub use self::filmstrip::Filmstrip;
+pub use self::gallery::Gallery;
 pub use self::image_set::ImageSet;
+pub use self::metadata_panel::MetadataPanel;
+pub use self::thumbnails::Thumbnails;
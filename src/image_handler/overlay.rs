@@ -0,0 +1,225 @@
+use crate::image_handler::histogram::Histogram;
+use std::fmt;
+use std::str::FromStr;
+
+// distance of the boxes drawn in a corner from the edges of the view
+const CORNER_MARGIN: f64 = 8.0;
+
+/// Corner of the view the OSD is drawn in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OsdPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for OsdPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(OsdPosition::TopLeft),
+            "top-right" => Ok(OsdPosition::TopRight),
+            "bottom-left" => Ok(OsdPosition::BottomLeft),
+            "bottom-right" => Ok(OsdPosition::BottomRight),
+            _ => Err(format!("unknown position `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for OsdPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OsdPosition::TopLeft => "top-left",
+            OsdPosition::TopRight => "top-right",
+            OsdPosition::BottomLeft => "bottom-left",
+            OsdPosition::BottomRight => "bottom-right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Draw the borders of the image pixels visible in `view`,
+/// `size` is the size of the image at `scale` with its top left corner at `origin`.
+pub fn draw_pixel_grid(
+    cr: &cairo::Context,
+    scale: f64,
+    origin: [i32; 2],
+    size: [i32; 2],
+    view: [i32; 2],
+) {
+    // visible part of the scaled image
+    let x0 = origin[0].max(0) as f64;
+    let y0 = origin[1].max(0) as f64;
+    let x1 = (origin[0] + size[0]).min(view[0]) as f64;
+    let y1 = (origin[1] + size[1]).min(view[1]) as f64;
+    if x0 >= x1 || y0 >= y1 {
+        return;
+    }
+
+    cr.save();
+    cr.set_line_width(1.0);
+    cr.set_source_rgba(0.5, 0.5, 0.5, 0.5);
+    // lines on half pixels are crisp one pixel wide
+    let mut col = ((x0 - origin[0] as f64) / scale).ceil() as i64;
+    loop {
+        let x = (origin[0] as f64 + col as f64 * scale).round() + 0.5;
+        if x > x1 {
+            break;
+        }
+        cr.move_to(x, y0);
+        cr.line_to(x, y1);
+        col += 1;
+    }
+    let mut row = ((y0 - origin[1] as f64) / scale).ceil() as i64;
+    loop {
+        let y = (origin[1] as f64 + row as f64 * scale).round() + 0.5;
+        if y > y1 {
+            break;
+        }
+        cr.move_to(x0, y);
+        cr.line_to(x1, y);
+        row += 1;
+    }
+    cr.stroke();
+    cr.restore();
+}
+
+/// Draw thin bars along the right and bottom edges showing which part
+/// of an image of `size` at `origin` is visible in `view`.
+pub fn draw_scroll_indicators(
+    cr: &cairo::Context,
+    origin: [i32; 2],
+    size: [i32; 2],
+    view: [i32; 2],
+) {
+    const THICKNESS: f64 = 4.0;
+    const MARGIN: f64 = 2.0;
+
+    cr.save();
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.5);
+    if size[0] > view[0] {
+        let track = view[0] as f64 - 2.0 * MARGIN - THICKNESS;
+        let start = -origin[0] as f64 / size[0] as f64 * track;
+        let len = view[0] as f64 / size[0] as f64 * track;
+        let y = view[1] as f64 - MARGIN - THICKNESS;
+        cr.rectangle(MARGIN + start, y, len, THICKNESS);
+    }
+    if size[1] > view[1] {
+        let track = view[1] as f64 - 2.0 * MARGIN - THICKNESS;
+        let start = -origin[1] as f64 / size[1] as f64 * track;
+        let len = view[1] as f64 / size[1] as f64 * track;
+        let x = view[0] as f64 - MARGIN - THICKNESS;
+        cr.rectangle(x, MARGIN + start, THICKNESS, len);
+    }
+    cr.fill_preserve();
+    cr.set_source_rgba(1.0, 1.0, 1.0, 0.7);
+    cr.set_line_width(1.0);
+    cr.stroke();
+    cr.restore();
+}
+
+/// Draw the lines of `text` on a translucent box in a corner of `view`.
+pub fn draw_osd(cr: &cairo::Context, text: &str, position: OsdPosition, view: [i32; 2]) {
+    const FONT_SIZE: f64 = 13.0;
+    const PADDING: f64 = 6.0;
+
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.is_empty() {
+        return;
+    }
+    cr.save();
+    cr.select_font_face("Sans", cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+    cr.set_font_size(FONT_SIZE);
+    let font = cr.font_extents();
+    let width = lines
+        .iter()
+        .map(|line| cr.text_extents(line).x_advance)
+        .fold(0.0, f64::max);
+    let box_width = width + 2.0 * PADDING;
+    let box_height = font.height * lines.len() as f64 + 2.0 * PADDING;
+    let [x, y] = corner_xy(position, [box_width, box_height], view);
+
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.6);
+    cr.rectangle(x.round(), y.round(), box_width.ceil(), box_height.ceil());
+    cr.fill();
+    cr.set_source_rgba(1.0, 1.0, 1.0, 0.9);
+    for (i, line) in lines.iter().enumerate() {
+        let baseline = y + PADDING + font.ascent + font.height * i as f64;
+        cr.move_to((x + PADDING).round(), baseline.round());
+        cr.show_text(line);
+    }
+    cr.restore();
+}
+
+/// Draw the RGB levels of `histogram` with the luma on top, in a corner of `view`.
+pub fn draw_histogram(
+    cr: &cairo::Context,
+    histogram: &Histogram,
+    position: OsdPosition,
+    view: [i32; 2],
+) {
+    const WIDTH: f64 = 256.0;
+    const HEIGHT: f64 = 100.0;
+    const PADDING: f64 = 6.0;
+
+    let max = histogram.max() as f64;
+    if max == 0.0 {
+        return;
+    }
+    let size = [WIDTH + 2.0 * PADDING, HEIGHT + 2.0 * PADDING];
+    let [x, y] = corner_xy(position, size, view);
+    let (x0, bottom) = ((x + PADDING).round(), (y + PADDING + HEIGHT).round());
+    let levels = |counts: &[u32; 256]| {
+        cr.move_to(x0, bottom);
+        for (level, &count) in counts.iter().enumerate() {
+            cr.line_to(
+                x0 + level as f64 + 0.5,
+                bottom - count as f64 / max * HEIGHT,
+            );
+        }
+        cr.line_to(x0 + WIDTH, bottom);
+    };
+
+    cr.save();
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.6);
+    cr.rectangle(x.round(), y.round(), size[0], size[1]);
+    cr.fill();
+    // overlapping channels add up to gray
+    cr.set_operator(cairo::Operator::Add);
+    let channels = [
+        (&histogram.red, [0.6, 0.0, 0.0]),
+        (&histogram.green, [0.0, 0.6, 0.0]),
+        (&histogram.blue, [0.0, 0.0, 0.6]),
+    ];
+    for (counts, [r, g, b]) in channels.iter() {
+        levels(counts);
+        cr.close_path();
+        cr.set_source_rgb(*r, *g, *b);
+        cr.fill();
+    }
+    cr.set_operator(cairo::Operator::Over);
+    levels(&histogram.luma);
+    cr.set_source_rgba(1.0, 1.0, 1.0, 0.9);
+    cr.set_line_width(1.0);
+    cr.stroke();
+    cr.restore();
+}
+
+/// Top left corner of a box of `size` in the `position` corner of `view`.
+fn corner_xy(position: OsdPosition, size: [f64; 2], view: [i32; 2]) -> [f64; 2] {
+    let x = match position {
+        OsdPosition::TopLeft | OsdPosition::BottomLeft => CORNER_MARGIN,
+        OsdPosition::TopRight | OsdPosition::BottomRight => {
+            view[0] as f64 - CORNER_MARGIN - size[0]
+        }
+    };
+    let y = match position {
+        OsdPosition::TopLeft | OsdPosition::TopRight => CORNER_MARGIN,
+        OsdPosition::BottomLeft | OsdPosition::BottomRight => {
+            view[1] as f64 - CORNER_MARGIN - size[1]
+        }
+    };
+    [x, y]
+}
@@ -0,0 +1,400 @@
+use gdk_pixbuf::Pixbuf;
+use image::codecs::hdr::HdrDecoder;
+use image::codecs::openexr::OpenExrDecoder;
+use image::{ColorType, ImageDecoder, ImageFormat};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// stop counting frames of damaged files with endless chains of frames
+const MAX_FRAMES: usize = 100_000;
+
+/// What the file header tells about an image, without decoding its pixels.
+///
+/// Dimensions and format come from the same gdk-pixbuf loaders that decode the image
+/// in the viewer, or from the decoders of high precision images gdk-pixbuf doesn't know.
+/// The pixel layout is read from the header of the common formats.
+pub struct Probe {
+    pub width: i32,
+    pub height: i32,
+    /// colour model of the stored pixels, e.g. `RGBA` or `indexed`
+    pub color_type: Option<&'static str>,
+    /// bits per sample, or per palette entry for indexed images
+    pub bit_depth: Option<u32>,
+    /// frames of an animation or pages of a multi-page TIFF
+    pub frames: usize,
+}
+
+impl Probe {
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let (_, width, height) = match Pixbuf::get_file_info(path) {
+            Some(info) => info,
+            None => return Self::decoder(path),
+        };
+        let mut file = BufReader::new(File::open(path).map_err(|err| err.to_string())?);
+        let mut magic = [0u8; 8];
+        let read = file.read(&mut magic).map_err(|err| err.to_string())?;
+        file.seek(SeekFrom::Start(0))
+            .map_err(|err| err.to_string())?;
+
+        let mut probe = Self {
+            width,
+            height,
+            color_type: None,
+            bit_depth: None,
+            frames: 1,
+        };
+        let magic = &magic[..read];
+        // a damaged header leaves the fields read so far
+        let _ = if magic.starts_with(b"\x89PNG\r\n\x1a\n") {
+            probe.png(&mut file)
+        } else if magic.starts_with(&[0xff, 0xd8]) {
+            probe.jpeg(&mut file)
+        } else if magic.starts_with(b"GIF8") {
+            probe.gif(&mut file)
+        } else if magic.starts_with(b"BM") {
+            probe.bmp(&mut file)
+        } else if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") {
+            probe.tiff(&mut file, magic[0] == b'I', true)
+        } else {
+            Ok(())
+        };
+        Ok(probe)
+    }
+
+    /// Bits per sample of the first page of a TIFF file, the other pages are not read.
+    pub fn tiff_bit_depth(path: &Path) -> Option<u32> {
+        let mut file = BufReader::new(File::open(path).ok()?);
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic).ok()?;
+        let mut probe = Self {
+            width: 0,
+            height: 0,
+            color_type: None,
+            bit_depth: None,
+            frames: 1,
+        };
+        // a damaged header leaves the depth read so far
+        let _ = probe.tiff(&mut file, magic[0] == b'I', false);
+        probe.bit_depth
+    }
+
+    /// Probe OpenEXR and Radiance files, the formats the viewer decodes without gdk-pixbuf.
+    fn decoder(path: &Path) -> Result<Self, String> {
+        let format = image::io::Reader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|err| err.to_string())?
+            .format();
+        let file = BufReader::new(File::open(path).map_err(|err| err.to_string())?);
+        let ((width, height), color) = match format {
+            Some(ImageFormat::OpenExr) => OpenExrDecoder::new(file)
+                .map(|decoder| (decoder.dimensions(), decoder.color_type())),
+            // RGBE pixels, decoded to floats
+            Some(ImageFormat::Hdr) => HdrDecoder::new(file).map(|decoder| {
+                let metadata = decoder.metadata();
+                ((metadata.width, metadata.height), ColorType::Rgb32F)
+            }),
+            _ => return Err(String::from("unknown image format")),
+        }
+        .map_err(|err| err.to_string())?;
+        let color_type = match color {
+            ColorType::L8 | ColorType::L16 => "grayscale",
+            ColorType::La8 | ColorType::La16 => "grayscale + alpha",
+            ColorType::Rgba8 | ColorType::Rgba16 | ColorType::Rgba32F => "RGBA",
+            _ => "RGB",
+        };
+        Ok(Self {
+            width: width as i32,
+            height: height as i32,
+            color_type: Some(color_type),
+            bit_depth: Some((color.bits_per_pixel() / color.channel_count() as u16) as u32),
+            frames: 1,
+        })
+    }
+
+    fn png<R: Read + Seek>(&mut self, file: &mut R) -> io::Result<()> {
+        // IHDR is the first chunk
+        file.seek(SeekFrom::Start(24))?;
+        let bit_depth = read_u8(file)?;
+        self.bit_depth = Some(bit_depth as u32);
+        self.color_type = match read_u8(file)? {
+            0 => Some("grayscale"),
+            2 => Some("RGB"),
+            3 => Some("indexed"),
+            4 => Some("grayscale + alpha"),
+            6 => Some("RGBA"),
+            _ => None,
+        };
+        // an animated PNG announces its frames before the image data
+        file.seek(SeekFrom::Start(8))?;
+        loop {
+            let len = read_u32(file, false)?;
+            let mut kind = [0u8; 4];
+            file.read_exact(&mut kind)?;
+            match &kind {
+                b"acTL" => {
+                    self.frames = read_u32(file, false)? as usize;
+                    return Ok(());
+                }
+                b"IDAT" | b"IEND" => return Ok(()),
+                _ => {
+                    file.seek(SeekFrom::Current(len as i64 + 4))?;
+                }
+            }
+        }
+    }
+
+    fn jpeg<R: Read + Seek>(&mut self, file: &mut R) -> io::Result<()> {
+        file.seek(SeekFrom::Start(2))?;
+        loop {
+            if read_u8(file)? != 0xff {
+                return Ok(());
+            }
+            let marker = read_u8(file)?;
+            // fill bytes before a marker
+            if marker == 0xff {
+                file.seek(SeekFrom::Current(-1))?;
+                continue;
+            }
+            let len = read_u16(file, false)?;
+            match marker {
+                // start of frame, except DHT, JPG and DAC which share the range
+                0xc0..=0xcf if marker != 0xc4 && marker != 0xc8 && marker != 0xcc => {
+                    self.bit_depth = Some(read_u8(file)? as u32);
+                    file.seek(SeekFrom::Current(4))?;
+                    self.color_type = match read_u8(file)? {
+                        1 => Some("grayscale"),
+                        3 => Some("YCbCr"),
+                        4 => Some("CMYK"),
+                        _ => None,
+                    };
+                    return Ok(());
+                }
+                0xda | 0xd9 => return Ok(()),
+                _ => {
+                    file.seek(SeekFrom::Current(len as i64 - 2))?;
+                }
+            }
+        }
+    }
+
+    fn gif<R: Read + Seek>(&mut self, file: &mut R) -> io::Result<()> {
+        self.color_type = Some("indexed");
+        file.seek(SeekFrom::Start(10))?;
+        let flags = read_u8(file)?;
+        file.seek(SeekFrom::Current(2))?;
+        if flags & 0x80 != 0 {
+            self.bit_depth = Some((flags & 0x07) as u32 + 1);
+            file.seek(SeekFrom::Current(3 << ((flags & 0x07) + 1)))?;
+        }
+        // every image descriptor is a frame
+        let mut frames = 0;
+        while frames < MAX_FRAMES {
+            match read_u8(file)? {
+                0x2c => {
+                    frames += 1;
+                    file.seek(SeekFrom::Current(8))?;
+                    let flags = read_u8(file)?;
+                    if flags & 0x80 != 0 {
+                        file.seek(SeekFrom::Current(3 << ((flags & 0x07) + 1)))?;
+                    }
+                    // LZW code size
+                    read_u8(file)?;
+                    skip_sub_blocks(file)?;
+                }
+                0x21 => {
+                    // extension label
+                    read_u8(file)?;
+                    skip_sub_blocks(file)?;
+                }
+                _ => break,
+            }
+            self.frames = frames;
+        }
+        Ok(())
+    }
+
+    fn bmp<R: Read + Seek>(&mut self, file: &mut R) -> io::Result<()> {
+        file.seek(SeekFrom::Start(28))?;
+        let bits = read_u16(file, true)? as u32;
+        self.color_type = match bits {
+            1 | 2 | 4 | 8 => Some("indexed"),
+            16 | 24 => Some("RGB"),
+            32 => Some("RGBA"),
+            _ => None,
+        };
+        self.bit_depth = match bits {
+            1 | 2 | 4 | 8 => Some(bits),
+            16 => Some(5),
+            24 | 32 => Some(8),
+            _ => None,
+        };
+        Ok(())
+    }
+
+    /// Read the first IFD, and count the pages of the other ones when `pages` is set.
+    fn tiff<R: Read + Seek>(&mut self, file: &mut R, le: bool, pages: bool) -> io::Result<()> {
+        const NEW_SUBFILE_TYPE: u16 = 254;
+        const BITS_PER_SAMPLE: u16 = 258;
+        const PHOTOMETRIC: u16 = 262;
+        const SAMPLES_PER_PIXEL: u16 = 277;
+        const EXTRA_SAMPLES: u16 = 338;
+
+        file.seek(SeekFrom::Start(4))?;
+        let mut offset = read_u32(file, le)?;
+        let mut ifds = 0;
+        let mut frames = 0;
+        // damaged files may point back at an IFD that was already read
+        let mut visited = HashSet::new();
+        while offset != 0 && ifds < MAX_FRAMES && visited.insert(offset) {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            let entries = read_u16(file, le)?;
+            // the first page describes the image
+            let first = ifds == 0;
+            let mut reduced = false;
+            let mut photometric = None;
+            let mut samples = 1;
+            let mut alpha = false;
+            for _ in 0..entries {
+                let tag = read_u16(file, le)?;
+                let kind = read_u16(file, le)?;
+                let count = read_u32(file, le)?;
+                let mut field = [0u8; 4];
+                file.read_exact(&mut field)?;
+                // up to two SHORT values are left aligned in the field, more are at an offset
+                let inline = kind == 3 && count <= 2;
+                let value = if inline {
+                    read_u16(&mut &field[..], le)? as u32
+                } else {
+                    read_u32(&mut &field[..], le)?
+                };
+                match tag {
+                    // thumbnails and other reduced versions of a page are not pages
+                    NEW_SUBFILE_TYPE => reduced = value & 1 != 0,
+                    _ if !first => (),
+                    // samples have the same depth
+                    BITS_PER_SAMPLE if inline => self.bit_depth = Some(value),
+                    BITS_PER_SAMPLE => {
+                        let here = file.stream_position()?;
+                        file.seek(SeekFrom::Start(value as u64))?;
+                        self.bit_depth = Some(read_u16(file, le)? as u32);
+                        file.seek(SeekFrom::Start(here))?;
+                    }
+                    PHOTOMETRIC => photometric = Some(value),
+                    SAMPLES_PER_PIXEL => samples = value,
+                    EXTRA_SAMPLES => alpha = true,
+                    _ => (),
+                }
+            }
+            if first {
+                self.color_type = match (photometric, alpha || samples == 2 || samples == 4) {
+                    (Some(0), false) | (Some(1), false) => Some("grayscale"),
+                    (Some(0), true) | (Some(1), true) => Some("grayscale + alpha"),
+                    (Some(2), false) => Some("RGB"),
+                    (Some(2), true) => Some("RGBA"),
+                    (Some(3), _) => Some("indexed"),
+                    (Some(5), _) => Some("CMYK"),
+                    (Some(6), _) => Some("YCbCr"),
+                    _ => None,
+                };
+            }
+            if first || !reduced {
+                frames += 1;
+            }
+            if !pages {
+                break;
+            }
+            ifds += 1;
+            offset = read_u32(file, le)?;
+        }
+        self.frames = frames.max(1);
+        Ok(())
+    }
+}
+
+fn skip_sub_blocks<R: Read + Seek>(file: &mut R) -> io::Result<()> {
+    loop {
+        let len = read_u8(file)?;
+        if len == 0 {
+            return Ok(());
+        }
+        file.seek(SeekFrom::Current(len as i64))?;
+    }
+}
+
+fn read_u8<R: Read>(file: &mut R) -> io::Result<u8> {
+    let mut buf = [0u8; 1];
+    file.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_u16<R: Read>(file: &mut R, le: bool) -> io::Result<u16> {
+    let mut buf = [0u8; 2];
+    file.read_exact(&mut buf)?;
+    Ok(if le {
+        u16::from_le_bytes(buf)
+    } else {
+        u16::from_be_bytes(buf)
+    })
+}
+
+fn read_u32<R: Read>(file: &mut R, le: bool) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    file.read_exact(&mut buf)?;
+    Ok(if le {
+        u32::from_le_bytes(buf)
+    } else {
+        u32::from_be_bytes(buf)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // little endian TIFF with one 16 bit IFD per entry of `next`, the offset of the IFD after it
+    fn tiff(next: &[u32]) -> Cursor<Vec<u8>> {
+        let mut data = b"II*\0\x08\0\0\0".to_vec();
+        for offset in next {
+            data.extend_from_slice(&1u16.to_le_bytes());
+            data.extend_from_slice(&[2, 1, 3, 0, 1, 0, 0, 0, 16, 0, 0, 0]);
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        Cursor::new(data)
+    }
+
+    fn probe() -> Probe {
+        Probe {
+            width: 1,
+            height: 1,
+            color_type: None,
+            bit_depth: None,
+            frames: 1,
+        }
+    }
+
+    #[test]
+    fn tiff_pages() {
+        let mut probe = probe();
+        probe.tiff(&mut tiff(&[26, 44, 0]), true, true).unwrap();
+        assert_eq!(probe.frames, 3);
+        assert_eq!(probe.bit_depth, Some(16));
+    }
+
+    #[test]
+    fn tiff_looping_offsets() {
+        let mut probe = probe();
+        probe.tiff(&mut tiff(&[26, 8]), true, true).unwrap();
+        assert_eq!(probe.frames, 2);
+    }
+
+    #[test]
+    fn tiff_first_page_only() {
+        let mut probe = probe();
+        probe.tiff(&mut tiff(&[26, 44, 0]), true, false).unwrap();
+        assert_eq!(probe.frames, 1);
+        assert_eq!(probe.bit_depth, Some(16));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::image_handler::loader::PixelData;
+use crate::image_handler::tiles::TiledRenderer;
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+/// High quality render of the visible part of the image,
+/// drawn instead of the tiles while the view does not change.
+pub struct RefinedView {
+    pub generation: usize,
+    pub scale: f64,
+    pub origin: [i32; 2],
+    pub view: [i32; 2],
+    /// position of the render relative to the image origin
+    pub offset: [i32; 2],
+    pub data: PixelData,
+}
+
+/// `RefinedView` handed over to the main thread.
+pub struct Refined {
+    pub scale: f64,
+    pub origin: [i32; 2],
+    pub view: [i32; 2],
+    pub offset: [i32; 2],
+    pub pixbuf: Pixbuf,
+}
+
+impl Refined {
+    pub fn new(view: RefinedView) -> Self {
+        Self {
+            scale: view.scale,
+            origin: view.origin,
+            view: view.view,
+            offset: view.offset,
+            pixbuf: view.data.to_pixbuf(),
+        }
+    }
+
+    /// The render is only valid for the exact view it was made for.
+    pub fn matches(&self, scale: f64, origin: [i32; 2], view: [i32; 2]) -> bool {
+        self.scale == scale && self.origin == origin && self.view == view
+    }
+}
+
+/// Result of the refine worker, delivered to the main loop through a glib channel.
+pub enum Refinement {
+    /// high quality render of the view
+    View(RefinedView),
+    /// mipmap levels of the image shown, each half the size of the previous one
+    Levels {
+        generation: usize,
+        levels: Vec<PixelData>,
+    },
+}
+
+struct Render {
+    generation: usize,
+    scale: f64,
+    origin: [i32; 2],
+    view: [i32; 2],
+    offset: [i32; 2],
+    size: [i32; 2],
+    src: PixelData,
+    src_offset: [f64; 2],
+    src_scale: [f64; 2],
+    interp: InterpType,
+}
+
+enum Job {
+    Render(Render),
+    Levels {
+        generation: usize,
+        source: PixelData,
+    },
+}
+
+/// Background thread rendering the visible region with a slow, high quality
+/// interpolation once interactive zooming has settled.
+/// It also builds the mipmap levels the tiles of a zoomed out view are scaled from.
+pub struct Refiner {
+    jobs: mpsc::Sender<Job>,
+    generation: Arc<AtomicUsize>,
+    // generation of the image the levels are built for
+    levels: Arc<AtomicUsize>,
+}
+
+impl Refiner {
+    pub fn new(results: glib::Sender<Refinement>) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let generation = Arc::new(AtomicUsize::new(0));
+        let levels = Arc::new(AtomicUsize::new(0));
+        let current = Arc::clone(&generation);
+        let current_levels = Arc::clone(&levels);
+        thread::spawn(move || {
+            for job in queue {
+                let result = match job {
+                    Job::Render(job) => {
+                        if job.generation != current.load(Ordering::SeqCst) {
+                            continue;
+                        }
+                        let data = match render(&job) {
+                            Some(data) => data,
+                            None => continue,
+                        };
+                        Refinement::View(RefinedView {
+                            generation: job.generation,
+                            scale: job.scale,
+                            origin: job.origin,
+                            view: job.view,
+                            offset: job.offset,
+                            data,
+                        })
+                    }
+                    Job::Levels { generation, source } => {
+                        let cancelled = || generation != current_levels.load(Ordering::SeqCst);
+                        match build_levels(&source, cancelled) {
+                            Some(levels) => Refinement::Levels { generation, levels },
+                            None => continue,
+                        }
+                    }
+                };
+                if results.send(result).is_err() {
+                    return;
+                }
+            }
+        });
+        Self {
+            jobs,
+            generation,
+            levels,
+        }
+    }
+
+    /// Queue a render of the part of the image visible in `view`,
+    /// cancelling the previous one.
+    pub fn request(
+        &self,
+        tiles: &TiledRenderer,
+        scale: f64,
+        origin: [i32; 2],
+        view: [i32; 2],
+        interp: InterpType,
+    ) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let [width, height] = tiles.scaled_size(scale);
+        // visible part of the scaled image
+        let x0 = (-origin[0]).max(0);
+        let y0 = (-origin[1]).max(0);
+        let x1 = (view[0] - origin[0]).min(width);
+        let y1 = (view[1] - origin[1]).min(height);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        // the part of the mipmap level covering it
+        let level = tiles.level(scale);
+        let sx = width as f64 / level.get_width() as f64;
+        let sy = height as f64 / level.get_height() as f64;
+        let lx0 = (x0 as f64 / sx).floor() as i32;
+        let ly0 = (y0 as f64 / sy).floor() as i32;
+        let lx1 = ((x1 as f64 / sx).ceil() as i32).min(level.get_width());
+        let ly1 = ((y1 as f64 / sy).ceil() as i32).min(level.get_height());
+        let src = match level
+            .new_subpixbuf(lx0, ly0, lx1 - lx0, ly1 - ly0)
+            .and_then(|sub| PixelData::from_pixbuf(&sub))
+        {
+            Some(src) => src,
+            None => return,
+        };
+
+        let job = Render {
+            generation,
+            scale,
+            origin,
+            view,
+            offset: [x0, y0],
+            size: [x1 - x0, y1 - y0],
+            src,
+            src_offset: [lx0 as f64 * sx - x0 as f64, ly0 as f64 * sy - y0 as f64],
+            src_scale: [sx, sy],
+            interp,
+        };
+        self.jobs.send(Job::Render(job)).unwrap();
+    }
+
+    /// Queue the mipmap levels of the image just shown, `source` is the full size level.
+    pub fn build_levels(&self, source: &Pixbuf) {
+        let generation = self.levels.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(source) = PixelData::from_pixbuf(source) {
+            self.jobs.send(Job::Levels { generation, source }).unwrap();
+        }
+    }
+
+    /// Drop the render and the levels in progress, e.g. when another image is shown.
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.levels.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn is_current(&self, generation: usize) -> bool {
+        generation == self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Whether the levels of `generation` belong to the image shown now.
+    pub fn are_levels_current(&self, generation: usize) -> bool {
+        generation == self.levels.load(Ordering::SeqCst)
+    }
+}
+
+/// Halve `source` until a side would be empty, `None` once cancelled.
+fn build_levels(source: &PixelData, cancelled: impl Fn() -> bool) -> Option<Vec<PixelData>> {
+    let mut levels = Vec::new();
+    let mut prev = source.to_pixbuf();
+    loop {
+        let width = prev.get_width() / 2;
+        let height = prev.get_height() / 2;
+        if width < 1 || height < 1 {
+            return Some(levels);
+        }
+        if cancelled() {
+            return None;
+        }
+        prev = prev.scale_simple(width, height, InterpType::Bilinear)?;
+        levels.push(PixelData::from_pixbuf(&prev)?);
+    }
+}
+
+fn render(job: &Render) -> Option<PixelData> {
+    let src = job.src.to_pixbuf();
+    let dest = Pixbuf::new(
+        Colorspace::Rgb,
+        src.get_has_alpha(),
+        8,
+        job.size[0],
+        job.size[1],
+    )?;
+    src.scale(
+        &dest,
+        0,
+        0,
+        job.size[0],
+        job.size[1],
+        job.src_offset[0],
+        job.src_offset[1],
+        job.src_scale[0],
+        job.src_scale[1],
+        job.interp,
+    );
+    PixelData::from_pixbuf(&dest)
+}
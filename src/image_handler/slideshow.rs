@@ -0,0 +1,123 @@
+use crate::config::Config;
+use std::time::{Duration, Instant};
+
+// how often the timer checks whether the next image is due
+pub const TICK_MS: u32 = 100;
+// shorter intervals are shown as long as this, in seconds
+const MIN_INTERVAL: f64 = 0.1;
+/// longest interval in seconds, a day
+pub const MAX_INTERVAL: f64 = 24.0 * 60.0 * 60.0;
+
+/// Interval of `seconds`, `None` unless it is above 0 and at most `MAX_INTERVAL`.
+pub fn interval(seconds: f64) -> Option<Duration> {
+    if seconds > 0.0 && seconds <= MAX_INTERVAL {
+        Some(Duration::from_secs_f64(seconds.max(MIN_INTERVAL)))
+    } else {
+        None
+    }
+}
+
+/// State of the slideshow, the images are advanced by a timer in the controller.
+#[derive(Debug)]
+pub struct Slideshow {
+    pub interval: Duration,
+    /// start over after the last image instead of stopping
+    pub looped: bool,
+    paused: bool,
+    // waiting for the image requested by the last step to be shown
+    waiting: bool,
+    shown_at: Instant,
+    timer: Option<glib::SourceId>,
+}
+
+impl Slideshow {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            // the configured interval was checked when it was read
+            interval: interval(config.slideshow_interval).unwrap_or(Duration::from_secs(5)),
+            looped: config.slideshow_loop,
+            paused: false,
+            waiting: false,
+            shown_at: Instant::now(),
+            timer: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.timer.is_some()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Start counting the interval from now, `timer` advances the images.
+    pub fn start(&mut self, timer: glib::SourceId) {
+        self.stop();
+        self.timer = Some(timer);
+        self.paused = false;
+        self.waiting = false;
+        self.shown_at = Instant::now();
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(id) = self.timer.take() {
+            glib::source_remove(id);
+        }
+        self.waiting = false;
+    }
+
+    /// The timer is forgotten without removing it, e.g. when its callback ends it.
+    pub fn stopped(&mut self) {
+        self.timer = None;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        // a resumed slideshow shows the current image for a whole interval
+        self.shown_at = Instant::now();
+    }
+
+    /// An image is on screen, the next one is due an interval later.
+    pub fn shown(&mut self) {
+        self.waiting = false;
+        self.shown_at = Instant::now();
+    }
+
+    /// Whether the timer should advance to the next image now.
+    pub fn is_due(&self) -> bool {
+        !self.paused && !self.waiting && self.shown_at.elapsed() >= self.interval
+    }
+
+    /// Whether the image about to be shown was requested by the slideshow.
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    /// The next image was requested, the interval restarts once it is shown.
+    pub fn advanced(&mut self) {
+        self.waiting = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_are_finite() {
+        assert_eq!(interval(2.5), Some(Duration::from_millis(2500)));
+        assert_eq!(interval(0.01), Some(Duration::from_millis(100)));
+        assert_eq!(interval(MAX_INTERVAL), Some(Duration::from_secs(86400)));
+        for seconds in [
+            0.0,
+            -1.0,
+            MAX_INTERVAL + 1.0,
+            1e300,
+            f64::INFINITY,
+            f64::NAN,
+        ] {
+            assert_eq!(interval(seconds), None, "{}", seconds);
+        }
+    }
+}
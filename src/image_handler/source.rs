@@ -0,0 +1,67 @@
+use crate::image_handler::hdr::HdrImage;
+use crate::image_handler::info::{FileInfo, ImageInfo};
+use crate::image_handler::tiles::TiledRenderer;
+use gdk_pixbuf::Pixbuf;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Decoded original of the current image.
+/// It is shared by all event handlers and every view of the image is rendered from it,
+/// the cache references the same pixbuf instead of keeping a copy.
+pub struct SourceImage {
+    pub pixbuf: Pixbuf,
+    pub tiles: TiledRenderer,
+    pub info: ImageInfo,
+    /// full precision pixels the pixbuf is rendered from, for high precision files,
+    /// or the gray values of an 8 bit grayscale image once they were examined
+    pub hdr: Option<Arc<HdrImage>>,
+}
+
+impl SourceImage {
+    /// `load_time` is the time spent decoding, `None` for a cached image.
+    pub fn new(
+        path: &Path,
+        pixbuf: Pixbuf,
+        hdr: Option<Arc<HdrImage>>,
+        file: FileInfo,
+        load_time: Option<Duration>,
+    ) -> Self {
+        let tiles = TiledRenderer::new(pixbuf.clone());
+        let info = ImageInfo::new(
+            path,
+            file,
+            pixbuf.get_width(),
+            pixbuf.get_height(),
+            load_time,
+        );
+        Self {
+            pixbuf,
+            tiles,
+            info,
+            hdr,
+        }
+    }
+
+    /// Show the pixels rendered again with other settings, the image keeps its size.
+    pub fn set_pixbuf(&mut self, pixbuf: Pixbuf) {
+        self.tiles = TiledRenderer::new(pixbuf.clone());
+        self.pixbuf = pixbuf;
+    }
+
+    pub fn width(&self) -> i32 {
+        self.pixbuf.get_width()
+    }
+
+    pub fn height(&self) -> i32 {
+        self.pixbuf.get_height()
+    }
+
+    /// Size of the image at `scale`.
+    pub fn scaled_size(&self, scale: f64) -> [i32; 2] {
+        [
+            (self.width() as f64 * scale).round() as i32,
+            (self.height() as f64 * scale).round() as i32,
+        ]
+    }
+}
@@ -0,0 +1,229 @@
+use gdk_pixbuf::{Colorspace, Pixbuf};
+use std::fs::{self, DirBuilder};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// failed thumbnails are recorded per application and version
+const FAIL_DIR: &str = concat!("iw-", env!("CARGO_PKG_VERSION"));
+
+/// Sizes of the freedesktop thumbnail standard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThumbnailSize {
+    Normal,
+    Large,
+    XLarge,
+}
+
+impl ThumbnailSize {
+    /// The smallest size at least as big as `pixels`.
+    pub fn for_pixels(pixels: i32) -> Self {
+        if pixels <= 128 {
+            ThumbnailSize::Normal
+        } else if pixels <= 256 {
+            ThumbnailSize::Large
+        } else {
+            ThumbnailSize::XLarge
+        }
+    }
+
+    pub fn pixels(self) -> i32 {
+        match self {
+            ThumbnailSize::Normal => 128,
+            ThumbnailSize::Large => 256,
+            ThumbnailSize::XLarge => 512,
+        }
+    }
+
+    fn dir(self) -> &'static str {
+        match self {
+            ThumbnailSize::Normal => "normal",
+            ThumbnailSize::Large => "large",
+            ThumbnailSize::XLarge => "x-large",
+        }
+    }
+}
+
+/// Thumbnails shared with other applications in `$XDG_CACHE_HOME/thumbnails`,
+/// following the freedesktop Thumbnail Managing Standard.
+///
+/// A thumbnail is named after the MD5 of the image URI and is valid as long as
+/// its `Thumb::URI` and `Thumb::MTime` match the image.
+#[derive(Debug, Clone)]
+pub struct ThumbnailCache {
+    root: PathBuf,
+}
+
+/// Identity of an image in the thumbnail cache.
+struct Entry {
+    uri: String,
+    mtime: String,
+    file_size: String,
+    name: String,
+}
+
+impl Entry {
+    fn new(path: &Path) -> Option<Self> {
+        let uri = glib::filename_to_uri(path, None).ok()?.to_string();
+        let metadata = fs::metadata(path).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            name: thumbnail_name(&uri)?,
+            uri,
+            mtime: mtime.as_secs().to_string(),
+            file_size: metadata.len().to_string(),
+        })
+    }
+
+    fn matches(&self, thumb: &Pixbuf) -> bool {
+        thumb.get_option("tEXt::Thumb::URI").as_deref() == Some(self.uri.as_str())
+            && thumb.get_option("tEXt::Thumb::MTime").as_deref() == Some(self.mtime.as_str())
+    }
+}
+
+impl ThumbnailCache {
+    pub fn new() -> Option<Self> {
+        let root = glib::get_user_cache_dir()?.join("thumbnails");
+        Some(Self { root })
+    }
+
+    /// Thumbnail of `path` from the cache, generated and stored when missing or outdated.
+    /// Images which failed before are not tried again until they change.
+    pub fn get(&self, path: &Path, size: ThumbnailSize) -> Result<Pixbuf, String> {
+        // the thumbnails themselves are not thumbnailed
+        let entry = match Entry::new(path) {
+            Some(entry) if !path.starts_with(&self.root) => entry,
+            _ => return generate(path, size),
+        };
+        let thumb_path = self.root.join(size.dir()).join(&entry.name);
+        if let Ok(thumb) = Pixbuf::from_file(&thumb_path) {
+            if entry.matches(&thumb) {
+                return Ok(thumb);
+            }
+        }
+        let fail_path = self.root.join("fail").join(FAIL_DIR).join(&entry.name);
+        if let Ok(fail) = Pixbuf::from_file(&fail_path) {
+            if entry.matches(&fail) {
+                return Err(String::from("thumbnail failed before"));
+            }
+        }
+
+        match generate(path, size) {
+            Ok(thumb) => {
+                if let Err(err) = self.store(&thumb, &thumb_path, &entry) {
+                    eprintln!("can't store thumbnail {:?}: {}", thumb_path, err);
+                }
+                Ok(thumb)
+            }
+            Err(err) => {
+                // a 1x1 image only carries the options of the failed image
+                if let Some(fail) = Pixbuf::new(Colorspace::Rgb, true, 8, 1, 1) {
+                    fail.fill(0);
+                    if let Err(err) = self.store(&fail, &fail_path, &entry) {
+                        eprintln!("can't store failed thumbnail {:?}: {}", fail_path, err);
+                    }
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Write a thumbnail to a temporary file renamed into place,
+    /// so that other applications never read a partial one.
+    fn store(&self, thumb: &Pixbuf, thumb_path: &Path, entry: &Entry) -> Result<(), String> {
+        let dir = thumb_path.parent().unwrap();
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(|err| err.to_string())?;
+        let width = thumb.get_width().to_string();
+        let height = thumb.get_height().to_string();
+        let options = [
+            ("tEXt::Thumb::URI", entry.uri.as_str()),
+            ("tEXt::Thumb::MTime", entry.mtime.as_str()),
+            ("tEXt::Thumb::Size", entry.file_size.as_str()),
+            ("tEXt::Thumb::Image::Width", width.as_str()),
+            ("tEXt::Thumb::Image::Height", height.as_str()),
+            ("tEXt::Software", "iw"),
+        ];
+        let tmp_path = dir.join(format!("{}.iw-{}", entry.name, std::process::id()));
+        thumb
+            .savev(&tmp_path, "png", &options)
+            .map_err(|err| err.to_string())?;
+        let stored = fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o600))
+            .and_then(|_| fs::rename(&tmp_path, thumb_path));
+        if let Err(err) = stored {
+            fs::remove_file(&tmp_path).ok();
+            return Err(err.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// File name of the thumbnail of `uri`, the MD5 of the URI in hex.
+fn thumbnail_name(uri: &str) -> Option<String> {
+    let md5 = glib::compute_checksum_for_string(glib::ChecksumType::Md5, uri)?;
+    Some(format!("{}.png", md5))
+}
+
+/// Decode `path` straight to thumbnail size, images smaller than it are kept as they are.
+/// Loaders such as JPEG skip most of the work for big images.
+pub fn generate(path: &Path, size: ThumbnailSize) -> Result<Pixbuf, String> {
+    let pixels = size.pixels();
+    let pixbuf = match Pixbuf::get_file_info(path) {
+        Some((_, width, height)) if width <= pixels && height <= pixels => Pixbuf::from_file(path),
+        _ => Pixbuf::from_file_at_scale(path, pixels, pixels, true),
+    };
+    let pixbuf = pixbuf.map_err(|err| err.to_string())?;
+    Ok(pixbuf.apply_embedded_orientation().unwrap_or(pixbuf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_round_up() {
+        assert_eq!(ThumbnailSize::for_pixels(64), ThumbnailSize::Normal);
+        assert_eq!(ThumbnailSize::for_pixels(128), ThumbnailSize::Normal);
+        assert_eq!(ThumbnailSize::for_pixels(129), ThumbnailSize::Large);
+        assert_eq!(ThumbnailSize::for_pixels(256), ThumbnailSize::Large);
+        assert_eq!(ThumbnailSize::for_pixels(1000), ThumbnailSize::XLarge);
+        assert_eq!(ThumbnailSize::XLarge.pixels(), 512);
+        assert_eq!(ThumbnailSize::XLarge.dir(), "x-large");
+    }
+
+    #[test]
+    fn names_are_the_md5_of_the_uri() {
+        // the example of the thumbnail standard
+        assert_eq!(
+            thumbnail_name("file:///home/jens/photos/me.png").as_deref(),
+            Some("c6ee772d9e49320e97ec29a7eb5b1697.png")
+        );
+    }
+
+    #[test]
+    fn entries_use_escaped_uris() {
+        let name = format!("iw thumb test {}.png", std::process::id());
+        let path = std::env::temp_dir().join(&name);
+        fs::write(&path, b"not really a png").unwrap();
+        let entry = Entry::new(&path);
+        fs::remove_file(&path).unwrap();
+        let entry = entry.unwrap();
+        assert!(entry.uri.starts_with("file:///"));
+        assert!(entry.uri.ends_with(&name.replace(' ', "%20")));
+        assert_eq!(Some(entry.name), thumbnail_name(&entry.uri));
+        assert_eq!(
+            thumbnail_name("file:///tmp/iw%20thumb%20test.png").unwrap(),
+            "1f1d54b4a809f5acf23f4d9e10b226dc.png"
+        );
+        assert_eq!(entry.file_size, "16");
+        assert!(entry.mtime.parse::<u64>().unwrap() > 0);
+    }
+
+    #[test]
+    fn missing_images_have_no_entry() {
+        assert!(Entry::new(Path::new("/nonexistent/iw/image.png")).is_none());
+    }
+}
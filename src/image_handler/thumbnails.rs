@@ -0,0 +1,221 @@
+use crate::config::Config;
+use crate::image_handler::cache::PixbufCache;
+use crate::image_handler::loader::PixelData;
+use crate::image_handler::thumbnail_cache::{self, ThumbnailCache, ThumbnailSize};
+use gdk::prelude::GdkContextExt;
+use gdk_pixbuf::Pixbuf;
+use glib::Continue;
+use gtk::WidgetExt;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Thumbnail of `path` delivered to the main loop through a glib channel.
+pub struct Thumbnail {
+    pub path: PathBuf,
+    pub size: ThumbnailSize,
+    pub result: Result<PixelData, String>,
+}
+
+struct Job {
+    path: PathBuf,
+    size: ThumbnailSize,
+    generation: usize,
+}
+
+/// Pool of worker threads generating thumbnails for the gallery and the filmstrip.
+///
+/// They only ask for the thumbnails they are about to draw,
+/// `cancel` drops the queued ones once they scrolled out of view.
+/// With a `ThumbnailCache` thumbnails are shared with other applications.
+pub struct Thumbnailer {
+    jobs: mpsc::Sender<Job>,
+    generation: Arc<AtomicUsize>,
+}
+
+impl Thumbnailer {
+    pub fn new(
+        workers: usize,
+        cache: Option<ThumbnailCache>,
+        results: glib::Sender<Thumbnail>,
+    ) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        let generation = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..workers.max(1) {
+            let queue = Arc::clone(&queue);
+            let generation = Arc::clone(&generation);
+            let results = results.clone();
+            let cache = cache.clone();
+            thread::spawn(move || loop {
+                let job = match queue.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    // thumbnailer was dropped
+                    Err(_) => return,
+                };
+                if job.generation != generation.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let pixbuf = match cache.as_ref() {
+                    Some(cache) => cache.get(&job.path, job.size),
+                    None => thumbnail_cache::generate(&job.path, job.size),
+                };
+                let result = pixbuf.and_then(|pixbuf| {
+                    PixelData::from_pixbuf(&pixbuf).ok_or_else(|| String::from("no pixel data"))
+                });
+                let thumbnail = Thumbnail {
+                    path: job.path,
+                    size: job.size,
+                    result,
+                };
+                if results.send(thumbnail).is_err() {
+                    return;
+                }
+            });
+        }
+
+        Self { jobs, generation }
+    }
+
+    pub fn request(&self, path: PathBuf, size: ThumbnailSize) {
+        let generation = self.generation.load(Ordering::SeqCst);
+        self.jobs
+            .send(Job {
+                path,
+                size,
+                generation,
+            })
+            .unwrap();
+    }
+
+    /// Drop every queued request, thumbnails being generated are still delivered.
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Thumbnails shared by the gallery and the filmstrip: one worker pool and one cache,
+/// so that every image is thumbnailed once for both.
+pub struct Thumbnails {
+    thumbnailer: Thumbnailer,
+    thumbs: Rc<RefCell<PixbufCache>>,
+    max_bytes: usize,
+    // size of the thumbnails in `thumbs`
+    size: Rc<RefCell<ThumbnailSize>>,
+    // requested since the last cancel
+    requested: RefCell<HashSet<PathBuf>>,
+    failed: Rc<RefCell<HashSet<PathBuf>>>,
+    // drawing areas showing the thumbnails, redrawn as they arrive
+    areas: Rc<RefCell<Vec<Rc<gtk::DrawingArea>>>>,
+}
+
+impl Thumbnails {
+    pub fn new(config: &Config) -> Self {
+        let (sender, thumbnails) = glib::MainContext::channel(glib::PRIORITY_DEFAULT_IDLE);
+        let cache = if config.shared_thumbnails {
+            ThumbnailCache::new()
+        } else {
+            None
+        };
+        let max_bytes = config.thumbnail_cache_mb * 1024 * 1024;
+        let thumbs = Rc::new(RefCell::new(PixbufCache::new(max_bytes, usize::MAX)));
+        let size = Rc::new(RefCell::new(ThumbnailSize::for_pixels(
+            config.thumbnail_size,
+        )));
+        let failed = Rc::new(RefCell::new(HashSet::new()));
+        let areas = Rc::new(RefCell::new(Vec::<Rc<gtk::DrawingArea>>::new()));
+
+        // store generated thumbnails and redraw
+        let store = Rc::clone(&thumbs);
+        let stored_size = Rc::clone(&size);
+        let failures = Rc::clone(&failed);
+        let redraw = Rc::clone(&areas);
+        thumbnails.attach(None, move |thumbnail: Thumbnail| {
+            // generated before the gallery moved to another size
+            if thumbnail.size != *stored_size.borrow() {
+                return Continue(true);
+            }
+            match thumbnail.result {
+                Ok(data) => store.borrow_mut().insert(thumbnail.path, data.to_pixbuf()),
+                Err(err) => {
+                    eprintln!("no thumbnail for {:?}: {}", thumbnail.path, err);
+                    failures.borrow_mut().insert(thumbnail.path);
+                }
+            }
+            for area in redraw.borrow().iter() {
+                area.queue_draw();
+            }
+            Continue(true)
+        });
+
+        Self {
+            thumbnailer: Thumbnailer::new(config.thumbnail_workers, cache, sender),
+            thumbs,
+            max_bytes,
+            size,
+            requested: RefCell::new(HashSet::new()),
+            failed,
+            areas,
+        }
+    }
+
+    /// Redraw `area` whenever a thumbnail arrives.
+    pub fn redraw(&self, area: Rc<gtk::DrawingArea>) {
+        self.areas.borrow_mut().push(area);
+    }
+
+    /// Thumbnail of `path` from the cache, requested when it is missing.
+    pub fn get(&self, path: &PathBuf) -> Option<Pixbuf> {
+        let thumb = self.thumbs.borrow_mut().get(path);
+        if thumb.is_none()
+            && !self.has_failed(path)
+            && self.requested.borrow_mut().insert(path.clone())
+        {
+            self.thumbnailer.request(path.clone(), *self.size.borrow());
+        }
+        thumb
+    }
+
+    pub fn has_failed(&self, path: &PathBuf) -> bool {
+        self.failed.borrow().contains(path)
+    }
+
+    /// Generate thumbnails of `size` from now on, the cached ones are dropped when it changes.
+    pub fn set_size(&self, size: ThumbnailSize) {
+        if size == *self.size.borrow() {
+            return;
+        }
+        *self.size.borrow_mut() = size;
+        *self.thumbs.borrow_mut() = PixbufCache::new(self.max_bytes, usize::MAX);
+        self.failed.borrow_mut().clear();
+        self.cancel();
+    }
+
+    /// Drop the queued requests, e.g. once their thumbnails scrolled out of view.
+    /// The areas still showing them ask again when they are drawn.
+    pub fn cancel(&self) {
+        self.thumbnailer.cancel();
+        self.requested.borrow_mut().clear();
+        for area in self.areas.borrow().iter() {
+            area.queue_draw();
+        }
+    }
+}
+
+/// Paint `thumb` fitted into the square of `size` at `x`, `y`, small images are not upscaled.
+pub fn paint(cr: &cairo::Context, thumb: &Pixbuf, x: f64, y: f64, size: f64) {
+    let (w, h) = (thumb.get_width() as f64, thumb.get_height() as f64);
+    let scale = (size / w).min(size / h).min(1.0);
+    cr.save();
+    cr.translate(x + (size - w * scale) / 2.0, y + (size - h * scale) / 2.0);
+    cr.scale(scale, scale);
+    cr.set_source_pixbuf(thumb, 0.0, 0.0);
+    cr.paint();
+    cr.restore();
+}
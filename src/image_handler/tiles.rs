@@ -0,0 +1,159 @@
+use gdk::prelude::GdkContextExt;
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use std::collections::{HashMap, VecDeque};
+
+/// Width and height of a rendered tile in screen pixels.
+pub const TILE_SIZE: i32 = 256;
+// 256 tiles of 256x256 RGBA are 64 MiB
+const MAX_TILES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TileKey {
+    tx: i32,
+    ty: i32,
+}
+
+/// Renders the visible part of an image tile by tile.
+///
+/// Only tiles intersecting the view are scaled, so zooming never allocates
+/// a pixbuf of the whole scaled image. Zoomed out views are scaled from
+/// mipmap levels (each half the size of the previous one) instead of the
+/// full resolution source.
+pub struct TiledRenderer {
+    // levels[0] is the source, deeper levels are built by the refine worker
+    levels: Vec<Pixbuf>,
+    tiles: HashMap<TileKey, Pixbuf>,
+    // least recently drawn first
+    order: VecDeque<TileKey>,
+    // scale and interpolation the cached tiles were rendered with
+    scale: f64,
+    interp: InterpType,
+}
+
+impl TiledRenderer {
+    pub fn new(source: Pixbuf) -> Self {
+        Self {
+            levels: vec![source],
+            tiles: HashMap::new(),
+            order: VecDeque::new(),
+            scale: 0.0,
+            interp: InterpType::Bilinear,
+        }
+    }
+
+    /// Draw the image at `scale` with its top left corner at `origin`
+    /// into a view of `view` size, only the visible tiles are rendered.
+    pub fn draw(
+        &mut self,
+        cr: &cairo::Context,
+        scale: f64,
+        origin: [i32; 2],
+        view: [i32; 2],
+        interp: InterpType,
+    ) {
+        if scale != self.scale || interp != self.interp {
+            self.tiles.clear();
+            self.order.clear();
+            self.scale = scale;
+            self.interp = interp;
+        }
+        let [width, height] = self.scaled_size(scale);
+        // visible part of the scaled image
+        let x0 = (-origin[0]).max(0);
+        let y0 = (-origin[1]).max(0);
+        let x1 = (view[0] - origin[0]).min(width);
+        let y1 = (view[1] - origin[1]).min(height);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        for ty in (y0 / TILE_SIZE)..=((y1 - 1) / TILE_SIZE) {
+            for tx in (x0 / TILE_SIZE)..=((x1 - 1) / TILE_SIZE) {
+                let tile = match self.tile(TileKey { tx, ty }) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                let x = (origin[0] + tx * TILE_SIZE) as f64;
+                let y = (origin[1] + ty * TILE_SIZE) as f64;
+                cr.set_source_pixbuf(&tile, x, y);
+                cr.rectangle(x, y, tile.get_width() as f64, tile.get_height() as f64);
+                cr.fill();
+            }
+        }
+    }
+
+    /// Size of the whole image at `scale`.
+    pub fn scaled_size(&self, scale: f64) -> [i32; 2] {
+        let source = &self.levels[0];
+        [
+            (source.get_width() as f64 * scale).round() as i32,
+            (source.get_height() as f64 * scale).round() as i32,
+        ]
+    }
+
+    fn tile(&mut self, key: TileKey) -> Option<Pixbuf> {
+        if let Some(tile) = self.tiles.get(&key) {
+            let tile = tile.clone();
+            if let Some(pos) = self.order.iter().position(|k| k == &key) {
+                self.order.remove(pos);
+            }
+            self.order.push_back(key);
+            return Some(tile);
+        }
+
+        let [width, height] = self.scaled_size(self.scale);
+        let tile_width = TILE_SIZE.min(width - key.tx * TILE_SIZE);
+        let tile_height = TILE_SIZE.min(height - key.ty * TILE_SIZE);
+        let level = self.level(self.scale);
+        let tile = Pixbuf::new(
+            Colorspace::Rgb,
+            level.get_has_alpha(),
+            8,
+            tile_width,
+            tile_height,
+        )?;
+        // scale the level into the tile, shifted so that only this tile's area lands in it
+        level.scale(
+            &tile,
+            0,
+            0,
+            tile_width,
+            tile_height,
+            -(key.tx * TILE_SIZE) as f64,
+            -(key.ty * TILE_SIZE) as f64,
+            width as f64 / level.get_width() as f64,
+            height as f64 / level.get_height() as f64,
+            self.interp,
+        );
+
+        if self.order.len() >= MAX_TILES {
+            if let Some(lru) = self.order.pop_front() {
+                self.tiles.remove(&lru);
+            }
+        }
+        self.order.push_back(key);
+        self.tiles.insert(key, tile.clone());
+        Some(tile)
+    }
+
+    /// Smallest mipmap level that is still at least as big as the image at `scale`,
+    /// so a tile is never downscaled by more than a factor of two once the levels are built.
+    pub fn level(&self, scale: f64) -> Pixbuf {
+        let mut level_scale = 1.0;
+        let mut n = 0;
+        while level_scale / 2.0 >= scale && n + 1 < self.levels.len() {
+            level_scale /= 2.0;
+            n += 1;
+        }
+        self.levels[n].clone()
+    }
+
+    /// Use the levels below the source built in the background,
+    /// the tiles are rendered again from them.
+    pub fn set_levels(&mut self, levels: Vec<Pixbuf>) {
+        self.levels.truncate(1);
+        self.levels.extend(levels);
+        self.tiles.clear();
+        self.order.clear();
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How the slideshow moves from one image to the next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransitionKind {
+    None,
+    Crossfade,
+    Slide,
+}
+
+impl FromStr for TransitionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(TransitionKind::None),
+            "crossfade" => Ok(TransitionKind::Crossfade),
+            "slide" => Ok(TransitionKind::Slide),
+            _ => Err(format!("unknown transition `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for TransitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TransitionKind::None => "none",
+            TransitionKind::Crossfade => "crossfade",
+            TransitionKind::Slide => "slide",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Transition in progress from a snapshot of the outgoing view to the incoming image.
+///
+/// The incoming image is drawn as usual between `push` and `pop`,
+/// the outgoing snapshot is composed on top of it.
+pub struct Transition {
+    kind: TransitionKind,
+    outgoing: cairo::ImageSurface,
+    duration_us: f64,
+    start: Option<i64>,
+    progress: f64,
+}
+
+impl Transition {
+    pub fn new(kind: TransitionKind, outgoing: cairo::ImageSurface, duration_ms: u32) -> Self {
+        Self {
+            kind,
+            outgoing,
+            duration_us: duration_ms.max(1) as f64 * 1000.0,
+            start: None,
+            progress: 0.0,
+        }
+    }
+
+    /// Advance to `frame_time` of the frame clock in µs, `true` once the transition is over.
+    pub fn update(&mut self, frame_time: i64) -> bool {
+        let start = *self.start.get_or_insert(frame_time);
+        let t = ((frame_time - start) as f64 / self.duration_us).min(1.0);
+        // ease in and out
+        self.progress = t * t * (3.0 - 2.0 * t);
+        t >= 1.0
+    }
+
+    /// Prepare `cr` for drawing the incoming image.
+    pub fn push(&self, cr: &cairo::Context, view: [i32; 2]) {
+        cr.save();
+        if self.kind == TransitionKind::Slide {
+            // the next image comes in from the right
+            cr.translate((1.0 - self.progress) * view[0] as f64, 0.0);
+        }
+    }
+
+    /// Compose the outgoing snapshot over the incoming image.
+    pub fn pop(&self, cr: &cairo::Context, view: [i32; 2]) {
+        cr.restore();
+        match self.kind {
+            TransitionKind::Crossfade => {
+                cr.set_source_surface(&self.outgoing, 0.0, 0.0);
+                cr.paint_with_alpha(1.0 - self.progress);
+            }
+            TransitionKind::Slide => {
+                let x = -self.progress * view[0] as f64;
+                cr.set_source_surface(&self.outgoing, x, 0.0);
+                cr.paint();
+            }
+            TransitionKind::None => (),
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+// narrowest window, one step of a 16 bit sample
+const MIN_WIDTH: f32 = 1.0 / 65535.0;
+// pixels of right-drag that double or halve the width
+const DRAG_DOUBLING: f64 = 200.0;
+// pixels of right-drag that move the level by the width of the window
+const DRAG_LEVEL: f64 = 400.0;
+
+/// Lookup table colouring the values of grayscale images.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lut {
+    Gray,
+    Viridis,
+    Inferno,
+    Jet,
+}
+
+impl Lut {
+    /// The table after this one, in the order the key cycles through them.
+    pub fn next(self) -> Self {
+        match self {
+            Lut::Gray => Lut::Viridis,
+            Lut::Viridis => Lut::Inferno,
+            Lut::Inferno => Lut::Jet,
+            Lut::Jet => Lut::Gray,
+        }
+    }
+
+    /// Colour of `t` from 0 to 1.
+    pub fn color(self, t: f32) -> [u8; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+        match self {
+            Lut::Gray => [byte(t); 3],
+            Lut::Viridis => interpolate(&VIRIDIS, t),
+            Lut::Inferno => interpolate(&INFERNO, t),
+            // blue over cyan, yellow and red, as in MATLAB
+            Lut::Jet => {
+                let ramp = |center: f32| byte(1.5 - (4.0 * t - center).abs());
+                [ramp(3.0), ramp(2.0), ramp(1.0)]
+            }
+        }
+    }
+}
+
+impl FromStr for Lut {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gray" => Ok(Lut::Gray),
+            "viridis" => Ok(Lut::Viridis),
+            "inferno" => Ok(Lut::Inferno),
+            "jet" => Ok(Lut::Jet),
+            _ => Err(format!("unknown lookup table `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Lut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Lut::Gray => "gray",
+            Lut::Viridis => "viridis",
+            Lut::Inferno => "inferno",
+            Lut::Jet => "jet",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// the matplotlib tables at every eighth
+const VIRIDIS: [[u8; 3]; 9] = [
+    [0x44, 0x01, 0x54],
+    [0x47, 0x2d, 0x7b],
+    [0x3b, 0x52, 0x8b],
+    [0x2c, 0x72, 0x8e],
+    [0x21, 0x91, 0x8c],
+    [0x28, 0xae, 0x80],
+    [0x5e, 0xc9, 0x62],
+    [0xad, 0xdc, 0x30],
+    [0xfd, 0xe7, 0x25],
+];
+const INFERNO: [[u8; 3]; 9] = [
+    [0x00, 0x00, 0x04],
+    [0x1f, 0x0c, 0x48],
+    [0x55, 0x0f, 0x6d],
+    [0x88, 0x22, 0x6a],
+    [0xba, 0x36, 0x55],
+    [0xe3, 0x59, 0x33],
+    [0xf9, 0x8e, 0x09],
+    [0xf9, 0xcb, 0x35],
+    [0xfc, 0xff, 0xa4],
+];
+
+/// Range of sample values stretched over the display range, as `level` ± `width` / 2.
+/// Values are the ones of the file scaled to 0..1, 16 bit samples are divided by 65535.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub level: f32,
+    pub width: f32,
+}
+
+impl Window {
+    /// Every value of an integer image, what is shown before any window is set.
+    pub const FULL: Window = Window {
+        level: 0.5,
+        width: 1.0,
+    };
+
+    /// Window from `low` to `high`.
+    pub fn between(low: f32, high: f32) -> Self {
+        Self {
+            level: (low + high) / 2.0,
+            width: (high - low).max(MIN_WIDTH),
+        }
+    }
+
+    /// Window spanning the `percentiles` (from 0 to 100) of `values`, NaNs are ignored.
+    pub fn from_percentiles(mut values: Vec<f32>, percentiles: [f32; 2]) -> Self {
+        values.retain(|value| !value.is_nan());
+        if values.is_empty() {
+            return Self::FULL;
+        }
+        let mut nth = |percentile: f32| {
+            let rank = (values.len() - 1) as f32 * percentile.clamp(0.0, 100.0) / 100.0;
+            let (_, value, _) = values.select_nth_unstable_by(rank.round() as usize, |a, b| {
+                a.partial_cmp(b).unwrap_or(Ordering::Equal)
+            });
+            *value
+        };
+        let low = nth(percentiles[0].min(percentiles[1]));
+        let high = nth(percentiles[0].max(percentiles[1]));
+        Self::between(low, high)
+    }
+
+    /// Position of `value` in the window from 0 to 1, clamped.
+    pub fn apply(self, value: f32) -> f32 {
+        ((value - self.level) / self.width + 0.5).clamp(0.0, 1.0)
+    }
+
+    /// The window after a right-drag by `dx`, `dy` pixels:
+    /// dragging right widens it and dragging down raises the level.
+    pub fn dragged(self, dx: f64, dy: f64) -> Self {
+        let width = self.width as f64 * 2f64.powf(dx / DRAG_DOUBLING);
+        let level = self.level as f64 + dy / DRAG_LEVEL * self.width as f64;
+        Self {
+            level: level as f32,
+            width: (width as f32).max(MIN_WIDTH),
+        }
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "level {:.4}, width {:.4}", self.level, self.width)
+    }
+}
+
+/// Colour at `t` of a table sampled at even steps.
+fn interpolate(table: &[[u8; 3]], t: f32) -> [u8; 3] {
+    let position = t * (table.len() - 1) as f32;
+    let i = (position.floor() as usize).min(table.len() - 2);
+    let fraction = position - i as f32;
+    let mut rgb = [0u8; 3];
+    for (c, value) in rgb.iter_mut().enumerate() {
+        let from = table[i][c] as f32;
+        let to = table[i + 1][c] as f32;
+        *value = (from + (to - from) * fraction + 0.5) as u8;
+    }
+    rgb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    fn ramp() -> Vec<f32> {
+        (0..=1000).map(|i| i as f32 / 1000.0).collect()
+    }
+
+    #[test]
+    fn window_from_percentiles() {
+        let window = Window::from_percentiles(ramp(), [1.0, 99.0]);
+        assert_close(window.level, 0.5);
+        assert_close(window.width, 0.98);
+        // the order of the percentiles doesn't matter
+        assert_eq!(Window::from_percentiles(ramp(), [99.0, 1.0]), window);
+    }
+
+    #[test]
+    fn percentiles_ignore_nan() {
+        let mut values = ramp();
+        values.resize(values.len() + 5000, f32::NAN);
+        let window = Window::from_percentiles(values, [0.0, 100.0]);
+        assert_close(window.level, 0.5);
+        assert_close(window.width, 1.0);
+        assert_eq!(
+            Window::from_percentiles(vec![f32::NAN], [0.0, 100.0]),
+            Window::FULL
+        );
+        assert_eq!(
+            Window::from_percentiles(Vec::new(), [0.0, 100.0]),
+            Window::FULL
+        );
+    }
+
+    #[test]
+    fn flat_image_keeps_a_minimal_width() {
+        let window = Window::from_percentiles(vec![0.25; 100], [1.0, 99.0]);
+        assert_close(window.level, 0.25);
+        assert_eq!(window.width, MIN_WIDTH);
+    }
+
+    #[test]
+    fn apply_stretches_and_clamps() {
+        let window = Window::between(0.2, 0.4);
+        assert_close(window.apply(0.2), 0.0);
+        assert_close(window.apply(0.3), 0.5);
+        assert_close(window.apply(0.4), 1.0);
+        assert_close(window.apply(-1.0), 0.0);
+        assert_close(window.apply(2.0), 1.0);
+    }
+
+    #[test]
+    fn drag_widens_and_moves_the_level() {
+        let window = Window::FULL.dragged(DRAG_DOUBLING, 0.0);
+        assert_close(window.width, 2.0);
+        assert_close(window.level, 0.5);
+        let window = Window::FULL.dragged(-DRAG_DOUBLING, DRAG_LEVEL);
+        assert_close(window.width, 0.5);
+        assert_close(window.level, 1.5);
+        assert_eq!(Window::FULL.dragged(-1e6, 0.0).width, MIN_WIDTH);
+    }
+
+    #[test]
+    fn lookup_tables() {
+        assert_eq!(Lut::Gray.color(0.5), [128; 3]);
+        assert_eq!(Lut::Viridis.color(0.0), VIRIDIS[0]);
+        assert_eq!(Lut::Viridis.color(1.0), VIRIDIS[8]);
+        assert_eq!(Lut::Inferno.color(0.5), INFERNO[4]);
+        assert_eq!(Lut::Jet.color(0.0), [0, 0, 128]);
+        assert_eq!(Lut::Jet.color(0.5), [128, 255, 128]);
+        assert_eq!(Lut::Jet.color(1.0), [128, 0, 0]);
+        // out of range and NaN values are clamped
+        assert_eq!(Lut::Inferno.color(2.0), INFERNO[8]);
+        assert_eq!(Lut::Viridis.color(f32::NAN), VIRIDIS[0]);
+    }
+
+    #[test]
+    fn lookup_table_names_round_trip() {
+        let mut lut = Lut::Gray;
+        for _ in 0..4 {
+            assert_eq!(lut.to_string().parse::<Lut>(), Ok(lut));
+            lut = lut.next();
+        }
+        assert_eq!(lut, Lut::Gray);
+        assert!("rainbow".parse::<Lut>().is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::config::Config;
+
+/// Zoom steps and limits.
+///
+/// Steps are multiplicative so that zooming feels the same at every
+/// magnification, a step never jumps over a preset level.
+#[derive(Debug, Clone)]
+pub struct Zoom {
+    step: f64,
+    // sorted ascending, within [min, max]
+    presets: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl Zoom {
+    pub fn new(config: &Config) -> Self {
+        let min = config.zoom_min.max(f64::EPSILON);
+        let max = config.zoom_max.max(min);
+        let mut presets: Vec<f64> = config
+            .zoom_presets
+            .iter()
+            .cloned()
+            .filter(|p| *p >= min && *p <= max)
+            .collect();
+        presets.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        presets.dedup();
+        Self {
+            step: config.zoom_step.max(1.01),
+            presets,
+            min,
+            max,
+        }
+    }
+
+    /// One step up from `scale`, snapping to a preset level on the way.
+    pub fn step_in(&self, scale: f64) -> f64 {
+        let next = scale * self.step;
+        let preset = self
+            .presets
+            .iter()
+            .find(|p| **p > scale * 1.001 && **p < next);
+        self.clamp(*preset.unwrap_or(&next))
+    }
+
+    /// One step down from `scale`, snapping to a preset level on the way.
+    pub fn step_out(&self, scale: f64) -> f64 {
+        let next = scale / self.step;
+        let preset = self
+            .presets
+            .iter()
+            .rev()
+            .find(|p| **p < scale / 1.001 && **p > next);
+        self.clamp(*preset.unwrap_or(&next))
+    }
+
+    /// The closest preset level above `scale`.
+    pub fn preset_in(&self, scale: f64) -> f64 {
+        let preset = self.presets.iter().find(|p| **p > scale * 1.001);
+        *preset.unwrap_or(&self.clamp(scale))
+    }
+
+    /// The closest preset level below `scale`.
+    pub fn preset_out(&self, scale: f64) -> f64 {
+        let preset = self.presets.iter().rev().find(|p| **p < scale / 1.001);
+        *preset.unwrap_or(&self.clamp(scale))
+    }
+
+    pub fn clamp(&self, scale: f64) -> f64 {
+        scale.max(self.min).min(self.max)
+    }
+}
+
+/// Parse a zoom percentage typed by the user, e.g. `150` or `150%`.
+pub fn parse_percentage(text: &str) -> Option<f64> {
+    let percent: f64 = text.trim().trim_end_matches('%').trim().parse().ok()?;
+    if percent.is_finite() && percent > 0.0 {
+        Some(percent / 100.0)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zoom(presets: &[f64], min: f64, max: f64) -> Zoom {
+        Zoom::new(&Config {
+            zoom_step: 1.5,
+            zoom_presets: presets.to_vec(),
+            zoom_min: min,
+            zoom_max: max,
+            ..Config::default()
+        })
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn presets_are_sorted_and_within_the_limits() {
+        let zoom = zoom(&[8.0, 1.0, 0.05, 1.0, 2.0], 0.1, 4.0);
+        assert_eq!(zoom.presets, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn steps_snap_to_presets() {
+        let zoom = zoom(&[0.5, 1.0, 2.0], 0.1, 4.0);
+        assert_close(zoom.step_in(0.9), 1.0);
+        assert_close(zoom.step_in(1.0), 1.5);
+        assert_close(zoom.step_in(1.5), 2.0);
+        assert_close(zoom.step_out(1.2), 1.0);
+        assert_close(zoom.step_out(2.0), 2.0 / 1.5);
+    }
+
+    #[test]
+    fn steps_stop_at_the_limits() {
+        let zoom = zoom(&[], 0.1, 4.0);
+        assert_close(zoom.step_in(3.5), 4.0);
+        assert_close(zoom.step_in(4.0), 4.0);
+        assert_close(zoom.step_out(0.11), 0.1);
+    }
+
+    #[test]
+    fn jumps_between_presets() {
+        let zoom = zoom(&[0.5, 1.0, 2.0], 0.1, 4.0);
+        assert_close(zoom.preset_in(0.7), 1.0);
+        assert_close(zoom.preset_in(1.0), 2.0);
+        assert_close(zoom.preset_out(1.0), 0.5);
+        // past the last preset the scale is kept
+        assert_close(zoom.preset_in(3.0), 3.0);
+        assert_close(zoom.preset_out(0.2), 0.2);
+    }
+
+    #[test]
+    fn percentages() {
+        assert_eq!(parse_percentage("150"), Some(1.5));
+        assert_eq!(parse_percentage(" 50 % "), Some(0.5));
+        assert_eq!(parse_percentage("0"), None);
+        assert_eq!(parse_percentage("-5"), None);
+        assert_eq!(parse_percentage("inf"), None);
+        assert_eq!(parse_percentage("big"), None);
+    }
+}
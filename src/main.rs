@@ -1,13 +1,29 @@
+/// `println!` only when the `IW_DEBUG` environment variable is set, stdout stays quiet otherwise.
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if crate::debug_enabled() {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod config;
 mod image_handler;
+mod print_info;
 
-use crate::image_handler::{Controller, ImageSet};
+use crate::config::Config;
+use crate::image_handler::slideshow;
+use crate::image_handler::{Controller, Filmstrip, Gallery, ImageSet, MetadataPanel, Thumbnails};
+use crate::print_info::print_info;
 use gdk_pixbuf::{PixbufLoader, PixbufLoaderExt};
-use gtk;
 use gtk::prelude::{BuilderExtManual, GtkWindowExt};
 use gtk::WidgetExt;
+use std::cell::RefCell;
 use std::env;
 use std::fs::read_to_string;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::OnceLock;
 
 /*
 https://gtk-rs.org/docs-src/tutorial/glade
@@ -21,7 +37,9 @@ http://www.kcjengr.com/programing/2017/10/16/dragable-gtk-widgets.html
 https://www.youtube.com/watch?v=u4YoV-hHu-k
 */
 
-const SUPPORTED_FORMATS: [&str; 7] = ["bmp", "png", "gif", "jpg", "jpeg", "tif", "tiff"];
+const SUPPORTED_FORMATS: [&str; 9] = [
+    "bmp", "png", "gif", "jpg", "jpeg", "tif", "tiff", "exr", "hdr",
+];
 
 // fn _main() {
 //     if gtk::init().is_err() {
@@ -51,32 +69,65 @@ const SUPPORTED_FORMATS: [&str; 7] = ["bmp", "png", "gif", "jpg", "jpeg", "tif",
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    println!("args: {:?}", args);
-    let image_path = match args.get(1) {
-        Some(path) => String::from(path),
-        None => String::from(args.get(0).unwrap()),
+    let mut image_paths = Vec::new();
+    let mut slideshow = None;
+    let mut info = false;
+    let mut json = false;
+    let mut options = args.iter().skip(1);
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--slideshow" => match options
+                .next()
+                .and_then(|seconds| seconds.parse().ok())
+                .and_then(slideshow::interval)
+            {
+                Some(interval) => slideshow = Some(interval),
+                None => {
+                    eprintln!(
+                        "--slideshow expects an interval in seconds up to a day, e.g. `--slideshow 5`"
+                    );
+                    std::process::exit(1);
+                }
+            },
+            "--info" => info = true,
+            "--json" => json = true,
+            _ => image_paths.push(arg.clone()),
+        }
+    }
+    // print what is known about the images and exit without a window
+    if info {
+        if image_paths.is_empty() {
+            eprintln!("--info expects image files, e.g. `iw --info a.jpg b.png`");
+            std::process::exit(1);
+        }
+        std::process::exit(if print_info(&image_paths, json) { 0 } else { 1 });
+    }
+    debug!("args: {:?}", args);
+    let image_path = match image_paths.into_iter().next() {
+        Some(path) => path,
+        None => String::from(args.first().unwrap()),
     };
     let image_path = Path::new(&image_path);
     let full_path = image_path.canonicalize().unwrap();
     let cur_dir = full_path.parent().unwrap();
-    println!("full_path: {:?}", full_path);
-    println!("cur_dir: {:?}", cur_dir);
+    debug!("full_path: {:?}", full_path);
+    debug!("cur_dir: {:?}", cur_dir);
     let images = std::fs::read_dir(cur_dir)
         .unwrap()
         .map(|res| res.unwrap().path())
         .filter(|res| {
             let ext = match res.extension() {
-                Some(ext) => String::from(ext.to_str().unwrap()),
+                Some(ext) => ext.to_string_lossy().into_owned(),
                 None => return false,
             };
             let supported_ext = SUPPORTED_FORMATS.contains(&ext.as_str());
             res.is_file() && supported_ext
         })
         .collect::<Vec<PathBuf>>();
-    println!("folder content: {:?}", images);
+    debug!("folder content: {:?}", images);
 
     if gtk::init().is_err() {
-        println!("Failed to initialize GTK");
+        eprintln!("Failed to initialize GTK");
         std::process::exit(1);
     }
     let glade_src = read_to_string("./src/iw.glade").unwrap();
@@ -91,22 +142,64 @@ fn main() {
             window.set_icon(Some(&icon));
         }
     }
-    pixbuf_loader.close().is_ok();
+    pixbuf_loader.close().ok();
 
-    let image: gtk::Image = builder.get_object("image").unwrap();
+    let canvas: gtk::DrawingArea = builder.get_object("canvas").unwrap();
     let layout: gtk::Layout = builder.get_object("layout").unwrap();
+    let spinner: gtk::Spinner = builder.get_object("spinner").unwrap();
+    let debug_label: gtk::Label = builder.get_object("debug_label").unwrap();
+    let stack: gtk::Stack = builder.get_object("stack").unwrap();
+    let gallery_area: gtk::DrawingArea = builder.get_object("gallery").unwrap();
+    let gallery_scrollbar: gtk::Scrollbar = builder.get_object("gallery_scrollbar").unwrap();
+    let thumbnail_size: gtk::Scale = builder.get_object("thumbnail_size").unwrap();
+    let filmstrip_area: gtk::DrawingArea = builder.get_object("filmstrip").unwrap();
+    let status_bar: gtk::Label = builder.get_object("status_bar").unwrap();
+    let metadata_panel: gtk::Box = builder.get_object("metadata_panel").unwrap();
+    let metadata_view: gtk::TreeView = builder.get_object("metadata_view").unwrap();
+    let metadata_filter: gtk::SearchEntry = builder.get_object("metadata_filter").unwrap();
+
+    let config = Config::load();
+    debug!("{:?}", config);
 
     // Controller
-    let image_set = ImageSet::new(images, &full_path);
-    let mut controller = Controller::new(window, image, image_set, layout);
-
-    println!("{:?}", controller.image_set);
+    let image_set = Rc::new(RefCell::new(ImageSet::new(images, &full_path)));
+    // the gallery and the filmstrip share the thumbnails
+    let thumbnails = Rc::new(Thumbnails::new(&config));
+    let gallery = Gallery::new(
+        stack,
+        gallery_area,
+        gallery_scrollbar,
+        thumbnail_size,
+        Rc::clone(&image_set),
+        Rc::clone(&thumbnails),
+        &config,
+    );
+    let filmstrip = Filmstrip::new(filmstrip_area, Rc::clone(&image_set), thumbnails, &config);
+    let metadata_panel = MetadataPanel::new(metadata_panel, metadata_view, metadata_filter);
+    let mut controller = Controller::new(
+        window,
+        canvas,
+        image_set,
+        layout,
+        spinner,
+        debug_label,
+        status_bar,
+        gallery,
+        filmstrip,
+        metadata_panel,
+        config,
+    );
+
+    debug!("{:?}", controller.image_set);
 
     controller.set_from_file(&full_path);
 
-    controller.window.set_title(full_path.to_str().unwrap());
+    controller.window.set_title(&full_path.to_string_lossy());
 
     controller.init_events();
+    if slideshow.is_some() {
+        controller.start_slideshow(slideshow);
+    }
 
     controller.window.connect_destroy(|_| gtk::main_quit());
 
@@ -121,3 +214,8 @@ fn main() {
     // println!("{}", gdk::Screen::width());
     // println!("{}", gdk::Screen::height());
 }
+
+fn debug_enabled() -> bool {
+    static ENABLED: OnceLock<bool> = OnceLock::new();
+    *ENABLED.get_or_init(|| env::var_os("IW_DEBUG").is_some())
+}
@@ -0,0 +1,161 @@
+use crate::image_handler::info::{format_size, FileInfo, ImageInfo};
+use crate::image_handler::metadata::{self, Group};
+use crate::image_handler::probe::Probe;
+use std::path::Path;
+
+// EXIF fields worth printing, as named by the metadata panel
+const KEY_EXIF_FIELDS: [&str; 12] = [
+    "Make",
+    "Model",
+    "LensModel",
+    "DateTimeOriginal",
+    "ExposureTime",
+    "FNumber",
+    "PhotographicSensitivity",
+    "FocalLength",
+    "FocalLengthIn35mmFilm",
+    "Flash",
+    "Orientation",
+    "GPSPosition",
+];
+
+/// Everything `iw --info` prints about one file.
+struct Report {
+    path: String,
+    info: ImageInfo,
+    probe: Probe,
+    exif: Vec<(String, String)>,
+}
+
+impl Report {
+    fn new(path: &Path) -> Result<Self, String> {
+        let probe = Probe::new(path)?;
+        let info = ImageInfo::new(path, FileInfo::read(path), probe.width, probe.height, None);
+        let exif = match metadata::read(path) {
+            Ok(fields) => fields
+                .into_iter()
+                .filter(|field| {
+                    field.group == Group::Exif && KEY_EXIF_FIELDS.contains(&field.name.as_str())
+                })
+                .map(|field| (field.name, field.value))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        Ok(Self {
+            path: info.path.clone(),
+            info,
+            probe,
+            exif,
+        })
+    }
+
+    fn print_text(&self) {
+        println!("{}", self.path);
+        let line = |key: &str, value: String| println!("  {:<24} {}", key, value);
+        line("format", self.info.format.clone().unwrap_or_default());
+        line(
+            "dimensions",
+            format!("{}x{}", self.probe.width, self.probe.height),
+        );
+        if let Some(color_type) = self.probe.color_type {
+            line("color type", String::from(color_type));
+        }
+        if let Some(bit_depth) = self.probe.bit_depth {
+            line("bit depth", bit_depth.to_string());
+        }
+        line("frames", self.probe.frames.to_string());
+        if let Some(size) = self.info.file_size {
+            line(
+                "file size",
+                format!("{} ({} bytes)", format_size(size), size),
+            );
+        }
+        for (name, value) in &self.exif {
+            line(name, value.clone());
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let mut fields = vec![
+            format!("\"path\": {}", json_string(&self.path)),
+            format!(
+                "\"format\": {}",
+                json_option(self.info.format.as_deref().map(json_string))
+            ),
+            format!("\"width\": {}", self.probe.width),
+            format!("\"height\": {}", self.probe.height),
+            format!(
+                "\"color_type\": {}",
+                json_option(self.probe.color_type.map(json_string))
+            ),
+            format!(
+                "\"bit_depth\": {}",
+                json_option(self.probe.bit_depth.map(|bits| bits.to_string()))
+            ),
+            format!("\"frames\": {}", self.probe.frames),
+            format!(
+                "\"file_size\": {}",
+                json_option(self.info.file_size.map(|size| size.to_string()))
+            ),
+        ];
+        let exif: Vec<String> = self
+            .exif
+            .iter()
+            .map(|(name, value)| format!("{}: {}", json_string(name), json_string(value)))
+            .collect();
+        fields.push(format!("\"exif\": {{{}}}", exif.join(", ")));
+        format!("{{{}}}", fields.join(", "))
+    }
+}
+
+/// Print what is known about every image of `paths` without opening a window,
+/// as text or as a JSON array with one object per file.
+/// Returns false when any of the files could not be read.
+pub fn print_info(paths: &[String], json: bool) -> bool {
+    let mut ok = true;
+    let mut objects = Vec::new();
+    for path in paths {
+        match Report::new(Path::new(path)) {
+            Ok(report) if json => objects.push(report.to_json()),
+            Ok(report) => report.print_text(),
+            Err(err) => {
+                ok = false;
+                if json {
+                    objects.push(format!(
+                        "{{\"path\": {}, \"error\": {}}}",
+                        json_string(path),
+                        json_string(&err)
+                    ));
+                } else {
+                    eprintln!("{}: {}", path, err);
+                }
+            }
+        }
+    }
+    if json {
+        println!("[{}]", objects.join(",\n "));
+    }
+    ok
+}
+
+fn json_option(value: Option<String>) -> String {
+    value.unwrap_or_else(|| String::from("null"))
+}
+
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}